@@ -0,0 +1,67 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use multi_var_poly::coef::*;
+use multi_var_poly::poly::*;
+use multi_var_poly::ring::*;
+use multi_var_poly::temp::*;
+
+// x1 + ... + xn + 1
+fn linear_sum(n: usize, r: &RingRef) -> Poly {
+    let mut p = Poly::one(r);
+    for i in 0..n {
+        let v = r.borrow_mut().vextend(format!("x{}", i));
+        p += Poly::from((v, r));
+    }
+    p
+}
+
+fn poly_add(c: &mut Criterion) {
+    let r = Ring::new();
+    let p = linear_sum(4, &r).pow(4);
+    let q = linear_sum(4, &r).pow(3) * Poly::from((C::new(-1, 1), &r));
+    c.bench_function("poly_add", |b| {
+        b.iter(|| black_box(p.clone()) + black_box(q.clone()))
+    });
+    c.bench_function("poly_sub", |b| {
+        b.iter(|| black_box(p.clone()) - black_box(p.clone()))
+    });
+}
+
+fn poly_mul(c: &mut Criterion) {
+    let r = Ring::new();
+    let p = linear_sum(4, &r).pow(3);
+    let q = linear_sum(4, &r).pow(2);
+    c.bench_function("poly_mul", |b| {
+        b.iter(|| black_box(p.clone()) * black_box(q.clone()))
+    });
+    let s = linear_sum(3, &r);
+    c.bench_function("poly_pow", |b| b.iter(|| black_box(&s).pow(6)));
+}
+
+// c_fall のループ本体を, 3 次の一般テンプレートに後ろから代入する
+fn temp_subs(c: &mut Criterion) {
+    let r = Ring::new();
+    let mut vars = vec![];
+    for s in &["x", "v", "t", "dt", "g", "a", "x0", "v0", "t0"] {
+        vars.push(r.borrow_mut().vextend(s.to_string()));
+    }
+    let (x, v, t, dt, g) = (vars[0], vars[1], vars[2], vars[3], vars[4]);
+    let p = |v: Var| Poly::from((v, &r));
+    let body = vec![
+        (t, p(t) + p(dt)),
+        (v, p(v) - p(g) * p(dt)),
+        (x, p(x) + p(v) * p(dt)),
+    ];
+    let tmp = Temp::most_gen(3, &r);
+    c.bench_function("temp_subs_c_fall", |b| {
+        b.iter(|| {
+            let mut t = black_box(tmp.clone());
+            for (lv, rv) in &body {
+                t = t.subs(*lv, rv.clone());
+            }
+            t
+        })
+    });
+}
+
+criterion_group!(benches, poly_add, poly_mul, temp_subs);
+criterion_main!(benches);
@@ -0,0 +1,89 @@
+use super::coef::*;
+use super::expr_parse::*;
+use super::poly::*;
+use super::print::*;
+use super::ring::*;
+
+/*
+ACSL Annotation
+*/
+// 元のソースの各 while の直前に /*@ loop invariant ...; */ を差し込む. Frama-C にそのまま渡せる.
+// 整数の上の式として読まれるので係数は整数にそろえる
+
+// invs[k] は先行順で k 番目のループの不変式 (どれも = 0). 空のループには何も書かない.
+// 整数にそろえた係数が i64 に収まらなければ CoefOverflow
+pub fn annotate(src: &str, e: &E, invs: &[Vec<Poly>], r: &RingRef) -> Result<String, CoefOverflow> {
+    let offsets = loop_offsets(e, src);
+    assert!(offsets.len() == invs.len(), "one invariant set per loop");
+    let pr = Printer::new(Syntax::C, r);
+    let mut res = src.to_string();
+    // 後ろから差し込めば前の位置はずれない
+    for (at, ps) in offsets.into_iter().zip(invs).rev() {
+        if ps.is_empty() {
+            continue;
+        }
+        let line = res[..at].rfind('\n').map_or(0, |i| i + 1);
+        let indent: String = res[line..]
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        let clauses = ps
+            .iter()
+            .map(|p| {
+                let p = p.try_primitive_part()?;
+                Ok(format!("loop invariant {} == 0;", pr.poly(&p)))
+            })
+            .collect::<Result<Vec<String>, CoefOverflow>>()?;
+        let comment = format!(
+            "/*@ {} */\n{}",
+            clauses.join(&format!("\n{}    ", indent)),
+            indent
+        );
+        res.insert_str(at, &comment);
+    }
+    Ok(res)
+}
+
+#[test]
+fn acsl_counter() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let src = r#"
+    x = 0; y = 0; z = 0;
+    while (x - n != 0) {
+        x = x + 1;
+        y = y + 2;
+    }
+    while (z - y != 0) { z = z + 1; }
+"#;
+    let (_, e) = program().parse(src).unwrap();
+    let _ = super::expr::convert_from_parseresult(&e, &r);
+    let v = |s: &str| Poly::from((r.borrow().var_by_name(s).unwrap(), &r));
+    let c = |n: i64, d: i64| Poly::from((super::coef::C::new(n, d), &r));
+    // 係数が分数でも整数にして書く
+    let invs = vec![vec![c(1, 2) * v("y") - v("x"), v("z")], vec![]];
+    let out = annotate(src, &e, &invs, &r).unwrap();
+    println!("{}", out);
+    let expected = r#"
+    x = 0; y = 0; z = 0;
+    /*@ loop invariant 2*x - y == 0;
+        loop invariant z == 0; */
+    while (x - n != 0) {
+        x = x + 1;
+        y = y + 2;
+    }
+    while (z - y != 0) { z = z + 1; }
+"#;
+    assert_eq!(out, expected);
+    // 注釈を入れても同じプログラムとして読める
+    let (rest, e2) = program().parse(&out).unwrap();
+    assert!(rest.trim().is_empty());
+    assert!(loop_offsets(&e2, &out).len() == 2);
+    // 分母の lcm が i64 を超えると, 整数係数にそろえられない
+    let big = c(1, 1_000_000_007) * v("x").pow(3)
+        + c(1, 998_244_353) * v("x").pow(2)
+        + c(1, 1_000_000_009) * v("x")
+        + c(1, 999_999_937);
+    let invs = vec![vec![big], vec![]];
+    assert!(annotate(src, &e, &invs, &r) == Err(CoefOverflow));
+}
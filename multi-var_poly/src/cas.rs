@@ -0,0 +1,275 @@
+use super::constraints::*;
+use super::ideal::*;
+use super::poly::*;
+use super::print::*;
+use super::ring::*;
+#[allow(unused_imports)]
+use super::temp::*;
+
+/*
+CAS Scripts
+*/
+// イデアルや連立方程式を Singular, Macaulay2, Sage のスクリプトにして, 外で Groebner 基底を比べる.
+// 変数は Var の番号順 (大きい順) に宣言するので, 単項式順序は grevlex でこちらと同じになる.
+// テンプレートのパラメーターは係数体 (有理関数体) の側に置く
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cas {
+    Singular,
+    Macaulay2,
+    Sage,
+}
+
+pub struct CasScript {
+    pub cas: Cas,
+    pr: Printer,
+    r: RingRef,
+    blocks: Vec<String>,
+}
+
+impl CasScript {
+    pub fn new(cas: Cas, r: &RingRef) -> CasScript {
+        CasScript {
+            cas,
+            pr: Printer::new(Syntax::Cas, r),
+            r: r.clone(),
+            blocks: vec![],
+        }
+    }
+
+    fn comment(&self, s: &str) -> String {
+        match self.cas {
+            Cas::Singular => format!("// {}", s),
+            Cas::Macaulay2 => format!("-- {}", s),
+            Cas::Sage => format!("# {}", s),
+        }
+    }
+
+    // 環 name + "Ring" を宣言し, 生成元 gens のイデアル name の Groebner 基底を出力する
+    fn block(
+        &mut self,
+        name: &str,
+        title: &str,
+        vars: &[String],
+        pars: &[String],
+        gens: &[String],
+    ) {
+        // Macaulay2 では _ も使えない
+        assert!(
+            name.chars().all(|c| c.is_ascii_alphanumeric()),
+            "bad name: {}",
+            name
+        );
+        let ring = format!("{}Ring", name);
+        let (vs, ps) = (vars.join(", "), pars.join(", "));
+        let gs = if gens.is_empty() {
+            "0".to_string()
+        } else {
+            gens.join(", ")
+        };
+        let mut lines = vec![self.comment(title)];
+        match self.cas {
+            Cas::Singular => {
+                let coef = if pars.is_empty() {
+                    "0".to_string()
+                } else {
+                    format!("(0, {})", ps)
+                };
+                lines.push(format!("ring {} = {}, ({}), dp;", ring, coef, vs));
+                lines.push(format!("ideal {} = {};", name, gs));
+                lines.push(format!("std({});", name));
+            }
+            Cas::Macaulay2 => {
+                let coef = if pars.is_empty() {
+                    "QQ".to_string()
+                } else {
+                    format!("frac(QQ[{}])", ps)
+                };
+                lines.push(format!(
+                    "{} = {}[{}, MonomialOrder => GRevLex];",
+                    ring, coef, vs
+                ));
+                let gs = if gens.is_empty() {
+                    format!("0_{}", ring)
+                } else {
+                    gs
+                };
+                lines.push(format!("{} = ideal({});", name, gs));
+                lines.push(format!("print gens gb {}", name));
+            }
+            Cas::Sage => {
+                let coef = if pars.is_empty() {
+                    "QQ".to_string()
+                } else {
+                    lines.push(format!(
+                        "{}Pars.<{}> = PolynomialRing(QQ, {})",
+                        name,
+                        ps,
+                        pars.len()
+                    ));
+                    format!("{}Pars.fraction_field()", name)
+                };
+                // 変数が一つでも多変数の環にする
+                lines.push(format!(
+                    "{}.<{}> = PolynomialRing({}, {}, order='degrevlex')",
+                    ring,
+                    vs,
+                    coef,
+                    vars.len()
+                ));
+                lines.push(format!("{} = {}.ideal([{}])", name, ring, gs));
+                lines.push(format!("print({}.groebner_basis())", name));
+            }
+        }
+        self.blocks.push(lines.join("\n"));
+    }
+
+    fn vars(&self) -> Vec<String> {
+        let vs = self.r.borrow().var_list();
+        vs.into_iter().map(|v| self.pr.var(v)).collect()
+    }
+
+    // 不変式 p = 0 の集まり
+    pub fn polys(&mut self, name: &str, ps: &[Poly]) -> &mut Self {
+        let gens: Vec<String> = ps.iter().map(|p| self.pr.poly(p)).collect();
+        let vars = self.vars();
+        self.block(name, "invariants", &vars, &[], &gens);
+        self
+    }
+
+    pub fn ideal(&mut self, name: &str, i: &Ideal) -> &mut Self {
+        self.polys(name, &i.gens)
+    }
+
+    pub fn pideal(&mut self, name: &str, i: &PIdeal) -> &mut Self {
+        let mut ts: Vec<&Temp> = i.gens.iter().collect();
+        ts.sort();
+        let mut pars: Vec<Par> = ts.iter().flat_map(|t| t.get_pars()).collect();
+        pars.sort();
+        pars.dedup();
+        let pars: Vec<String> = pars.into_iter().map(|p| self.pr.par(p)).collect();
+        let gens: Vec<String> = ts.iter().map(|t| self.pr.temp(t)).collect();
+        let vars = self.vars();
+        self.block(name, "parametric ideal", &vars, &pars, &gens);
+        self
+    }
+
+    // Σ a p = c を a p - c として, パラメーターの環のイデアルにする
+    pub fn equations(&mut self, name: &str, le: &LinearEquations) -> &mut Self {
+        let rows = le.rows();
+        let mut pars: Vec<Par> = rows
+            .iter()
+            .flat_map(|(l, _)| l.terms.iter().filter_map(|pt| pt.par))
+            .collect();
+        pars.sort();
+        pars.dedup();
+        let pars: Vec<String> = pars.into_iter().map(|p| self.pr.par(p)).collect();
+        let mut gens: Vec<String> = rows
+            .into_iter()
+            .map(|(l, c)| self.pr.lin(&(l + (-c))))
+            .collect();
+        gens.sort();
+        self.block(name, "linear equations", &pars, &[], &gens);
+        self
+    }
+
+    pub fn build(&self) -> String {
+        let mut blocks = self.blocks.clone();
+        if self.cas == Cas::Singular {
+            // 簡約 Groebner 基底を出させる
+            blocks.insert(0, "option(redSB);".to_string());
+        }
+        blocks.join("\n\n") + "\n"
+    }
+}
+
+#[test]
+fn cas_scripts() {
+    use super::coef::*;
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+    let c = |n, d| Poly::from((C::new(n, d), &r));
+    let invs = vec![px.clone() * py.clone() - c(1, 2), py.pow(2) - px.clone()];
+    let _scope = r.par_scope();
+    let t = Temp::most_gen(1, &r);
+    let pars = t.get_pars();
+    let le = LinearEquations::from((
+        Cs::new().add(Constraint(PIdeal::from(t.clone()), PIdeal::zero(&r))),
+        &r,
+    ));
+    let show = |cas| {
+        let mut s = CasScript::new(cas, &r);
+        s.polys("inv", &invs)
+            .pideal("head", &PIdeal::from(t.clone()))
+            .equations("eqs", &le);
+        let s = s.build();
+        println!("{}", s);
+        s
+    };
+    let a = |i: usize| format!("a{}", pars[i].id);
+    let s = show(Cas::Singular);
+    assert!(s.starts_with("option(redSB);"));
+    assert!(s.contains("ring invRing = 0, (x, y), dp;\nideal inv = x*y - 1/2, y^2 - x;\nstd(inv);"));
+    assert!(s.contains(&format!(
+        "ring headRing = (0, {}, {}, {}), (x, y), dp;",
+        a(0),
+        a(1),
+        a(2)
+    )));
+    assert!(s.contains(&format!(
+        "ring eqsRing = 0, ({}, {}, {}), dp;",
+        a(0),
+        a(1),
+        a(2)
+    )));
+    let s = show(Cas::Macaulay2);
+    assert!(s.contains("invRing = QQ[x, y, MonomialOrder => GRevLex];"));
+    assert!(s.contains("inv = ideal(x*y - 1/2, y^2 - x);\nprint gens gb inv"));
+    assert!(s.contains(&format!(
+        "headRing = frac(QQ[{}, {}, {}])[x, y",
+        a(0),
+        a(1),
+        a(2)
+    )));
+    let s = show(Cas::Sage);
+    assert!(s.contains("invRing.<x, y> = PolynomialRing(QQ, 2, order='degrevlex')"));
+    assert!(s.contains("inv = invRing.ideal([x*y - 1/2, y^2 - x])\nprint(inv.groebner_basis())"));
+    assert!(s.contains(&format!(
+        "headPars.<{}, {}, {}> = PolynomialRing(QQ, 3)",
+        a(0),
+        a(1),
+        a(2)
+    )));
+    assert!(s.contains("headRing.<x, y> = PolynomialRing(headPars.fraction_field(), 2"));
+    // テンプレートを 0 にする方程式は, 係数がみな 0
+    assert!(s.contains(&format!(
+        "eqs = eqsRing.ideal([-{}, -{}, -{}])",
+        a(0),
+        a(1),
+        a(2)
+    )));
+}
+
+#[test]
+fn cas_name_clash() {
+    // a[0] と同じ名前になる変数
+    let r = Ring::new();
+    let a0 = r.borrow_mut().vextend("a0".to_string());
+    let _scope = r.par_scope();
+    let t = Temp::most_gen(1, &r);
+    let pars = t.get_pars();
+    let pr = Printer::new(Syntax::Cas, &r);
+    let names: Vec<String> = pars.iter().map(|p| pr.par(*p)).collect();
+    println!("{:?}", names);
+    assert!(pars[0].id == 0 && names[0] != pr.var(a0));
+    let mut s = CasScript::new(Cas::Singular, &r);
+    s.pideal("head", &PIdeal::from(t.clone()));
+    let s = s.build();
+    println!("{}", s);
+    assert!(s.contains(&format!(
+        "ring headRing = (0, {}, {}), (a0), dp;",
+        names[0], names[1]
+    )));
+}
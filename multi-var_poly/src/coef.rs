@@ -36,6 +36,18 @@ impl std::fmt::Debug for ParTerm {
 
 impl Eq for ParTerm {}
 
+impl ParTerm {
+    // Debug と同じ形で, パラメーターは環の名前で表示する
+    pub fn show(&self, r: &Ring) -> String {
+        match self.par {
+            None => format!("{}", self.coef),
+            Some(p) if self.coef == C::one() => r.par_name(p),
+            Some(p) if self.coef == -C::one() => format!("-{}", r.par_name(p)),
+            Some(p) => format!("{}{}", self.coef, r.par_name(p)),
+        }
+    }
+}
+
 impl ParTerm {
     pub fn zero() -> Self {
         ParTerm {
@@ -148,6 +160,20 @@ impl std::fmt::Debug for LinExp {
 }
 
 impl LinExp {
+    pub fn show(&self, r: &Ring) -> String {
+        assert!(!self.terms.is_empty());
+        let mut res = self.terms[0].show(r);
+        if !self.is_cnst() {
+            for t in &self.terms[1..] {
+                if t.coef > C::zero() {
+                    res = format!("{}+{}", res, t.show(r));
+                } else {
+                    res = format!("{}{}", res, t.show(r));
+                }
+            }
+        }
+        format!("({})", res)
+    }
     fn sort_sumup(&mut self) {
         // 0を追加して, 最後にまとめて消す
         let z = ParTerm::zero();
@@ -335,6 +361,22 @@ fn linexp_ops_test() {
     assert!(LinExp::one() + LinExp::zero() == LinExp::one());
 }
 
+/*
+Coefficient Overflow
+*/
+// 多倍長で計算した結果が C (分子, 分母とも i64) に収まらない
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CoefOverflow;
+
+impl std::fmt::Display for CoefOverflow {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "coefficient does not fit in i64")
+    }
+}
+
+impl std::error::Error for CoefOverflow {}
+
 /*
 
 */
@@ -356,8 +398,21 @@ pub trait Coef:
     + Zero
     + Hash
 {
+    // 環の情報 (パラメーター名) を使って表示する
+    fn show(&self, _r: &Ring) -> String {
+        format!("{:?}", self)
+    }
 }
 
-impl Coef for LinExp {}
+impl Coef for LinExp {
+    fn show(&self, r: &Ring) -> String {
+        LinExp::show(self, r)
+    }
+}
 
-impl Coef for C {}
+impl Coef for C {
+    // Debug だと Ratio { numer: .. } になる
+    fn show(&self, _r: &Ring) -> String {
+        format!("{}", self)
+    }
+}
@@ -6,10 +6,9 @@ use super::poly::*;
 use super::poly_parse::*;
 use super::ring::*;
 use super::temp::*;
-use std::cell::RefCell;
-use std::collections::HashSet;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::hash::{Hash, Hasher};
-use std::rc::Rc;
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct PIdeal {
     pub gens: HashSet<Temp>,
@@ -37,12 +36,12 @@ impl PIdeal {
             gens: HashSet::new(),
         }
     }
-    pub fn zero(r: &Rc<RefCell<Ring>>) -> PIdeal {
+    pub fn zero(r: &RingRef) -> PIdeal {
         let mut i = HashSet::new();
         i.insert(Temp::zero(r));
         PIdeal { gens: i }
     }
-    pub fn most_gen(d: usize, r: &Rc<RefCell<Ring>>) -> PIdeal {
+    pub fn most_gen(d: usize, r: &RingRef) -> PIdeal {
         let mut gens = HashSet::new();
         gens.insert(Temp::most_gen(d, r));
         PIdeal { gens }
@@ -55,10 +54,30 @@ impl PIdeal {
         self
     }
 
-    fn rem_par(mut self, divisor: &Poly) -> PIdeal {
+    fn rem_par(self, divisor: &Poly) -> PIdeal {
+        self.rem_with(divisor, RemMode::Quotient, &mut GenStats::default())
+    }
+
+    fn rem_with(mut self, divisor: &Poly, mode: RemMode, stats: &mut GenStats) -> PIdeal {
         let mut new_gens = HashSet::new();
         for g in self.gens {
-            new_gens.insert(g.rem_par(divisor.clone()));
+            stats.divisions += 1;
+            match g.rem_with(divisor, mode) {
+                Ok(Rem { rem, fresh }) => {
+                    stats.fresh += fresh;
+                    new_gens.insert(rem);
+                }
+                // 掛けられる商は 0 だけ
+                Err(RemError::DegreeTooHigh { .. }) => {
+                    stats.too_high += 1;
+                    new_gens.insert(g);
+                }
+                // y - y == 0 のようにガードが 0 なら, 分岐はいつも同じ側に行く.
+                // 0 で割った余りは g のままで, 掛ける方は 0 になるのでそれで合う
+                Err(RemError::ZeroDivisor) => {
+                    new_gens.insert(g);
+                }
+            }
         }
         self.gens = new_gens;
         self
@@ -66,6 +85,11 @@ impl PIdeal {
 
     fn mul(mut self, other: &Poly) -> PIdeal {
         let mut new_gens = HashSet::new();
+        // 0 を掛けた生成元は何も言わない. 残すと cs_eqs で他の生成元まで 0 にしてしまう
+        if other.is_zero() {
+            self.gens = new_gens;
+            return self;
+        }
         for g in self.gens {
             new_gens.insert(g * other.clone());
         }
@@ -108,8 +132,30 @@ impl Cs {
     }
 }
 
+// 制約を作る間の記録
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GenStats {
+    pub divisions: usize,
+    // 商のために増えたパラメーターの数
+    pub fresh: usize,
+    // ガードの次数がテンプレートより高く, 割らずに済ませた回数
+    pub too_high: usize,
+    // ループ頭のイデアル. 後ろから処理するので, ソースでの順 (先行順) の逆に積む
+    pub heads: Vec<PIdeal>,
+}
+
 // Generating Constraints
-pub fn gen_con(e: &Expr, mut ideal: PIdeal, mut c: Cs) -> (PIdeal, Cs) {
+pub fn gen_con(e: &Expr, ideal: PIdeal, c: Cs) -> (PIdeal, Cs) {
+    gen_con_with(e, ideal, c, RemMode::Quotient, &mut GenStats::default())
+}
+
+pub fn gen_con_with(
+    e: &Expr,
+    mut ideal: PIdeal,
+    mut c: Cs,
+    mode: RemMode,
+    stats: &mut GenStats,
+) -> (PIdeal, Cs) {
     match e {
         Expr::Ass { lv, rv } => {
             let mut new_gens = HashSet::new();
@@ -119,36 +165,101 @@ pub fn gen_con(e: &Expr, mut ideal: PIdeal, mut c: Cs) -> (PIdeal, Cs) {
             ideal.gens = new_gens;
             (ideal, c)
         }
+        Expr::ParAss { lvs, rvs } => {
+            let map = lvs.iter().cloned().zip(rvs.iter().cloned()).collect();
+            ideal.gens = ideal.gens.iter().map(|tp| tp.subs_many(&map)).collect();
+            (ideal, c)
+        }
         Expr::Skip => (ideal, c),
         Expr::Seq { exprs } => {
             for i in (0..exprs.len()).rev() {
-                let next_ic = gen_con(&exprs[i], ideal, c);
+                let next_ic = gen_con_with(&exprs[i], ideal, c, mode, stats);
                 ideal = next_ic.0;
                 c = next_ic.1;
             }
             (ideal, c)
         }
         Expr::If { guard, the, els } => {
-            let (i1, c1) = gen_con(the, ideal.clone(), c.clone());
-            let (i2, c2) = gen_con(els, ideal, c);
-            match guard {
-                Pred { p, eq } if *eq => {
-                    let i1remp = i1.rem_par(p);
-                    let i2p = i2.mul(p);
-                    (i1remp.union(i2p), c1.union(c2))
-                }
-                Pred { p, .. } => {
-                    let i2remp = i2.rem_par(p);
-                    let i1p = i1.mul(p);
-                    (i2remp.union(i1p), c1.union(c2))
+            let start = stats.heads.len();
+            let (i1, c1) = gen_con_with(the, ideal.clone(), c.clone(), mode, stats);
+            let mid = stats.heads.len();
+            let (i2, c2) = gen_con_with(els, ideal, c, mode, stats);
+            // else 側のループを先に並べて, 逆順にしたとき then 側が先に来るようにする
+            stats.heads[start..].rotate_left(mid - start);
+            (if_ideal(guard, i1, i2, mode, stats), c1.union(c2))
+        }
+        Expr::While { c: body, .. } => {
+            let (i1, c1) = gen_con_with(body, ideal.clone(), c.clone(), mode, stats);
+            stats.heads.push(ideal.clone());
+            c = c.add(Constraint(ideal.clone(), i1));
+            (ideal, c.union(c1))
+        }
+    }
+}
+
+// If の両腕から来たイデアルを, ガードで割ったり掛けたりしてまとめる
+fn if_ideal(guard: &Pred, i1: PIdeal, i2: PIdeal, mode: RemMode, stats: &mut GenStats) -> PIdeal {
+    match guard {
+        Pred { p, eq } if *eq => {
+            let i1remp = i1.rem_with(p, mode, stats);
+            let i2p = i2.mul(p);
+            i1remp.union(i2p)
+        }
+        Pred { p, .. } => {
+            let i2remp = i2.rem_with(p, mode, stats);
+            let i1p = i1.mul(p);
+            i2remp.union(i1p)
+        }
+    }
+}
+
+// gen_con と同じ制約を, 複数スレッドで生成する.
+// If の両腕は独立に処理できる. ループはイデアルをそのまま返すので,
+// 本体の制約生成は Seq の残りと並行して走らせてよい.
+// パラメーターの番号付けはスレッドの実行順で変わる.
+pub fn gen_con_par(e: &Expr, mut ideal: PIdeal, mut c: Cs) -> (PIdeal, Cs) {
+    match e {
+        Expr::If { guard, the, els } => {
+            let ((i1, c1), (i2, c2)) = std::thread::scope(|s| {
+                let inv = ideal.clone();
+                let h = s.spawn(move || gen_con_par(the, inv, Cs::new()));
+                let res2 = gen_con_par(els, ideal, Cs::new());
+                (h.join().expect("gen_con_par thread panicked"), res2)
+            });
+            (
+                if_ideal(guard, i1, i2, RemMode::Quotient, &mut GenStats::default()),
+                c.union(c1).union(c2),
+            )
+        }
+        Expr::Seq { exprs } => std::thread::scope(|s| {
+            let mut handles = vec![];
+            for e in exprs.iter().rev() {
+                match e {
+                    Expr::While { c: body, .. } => {
+                        let inv = ideal.clone();
+                        handles.push(s.spawn(move || {
+                            let (i1, c1) = gen_con_par(body, inv.clone(), Cs::new());
+                            c1.add(Constraint(inv, i1))
+                        }));
+                    }
+                    _ => {
+                        let next_ic = gen_con_par(e, ideal, c);
+                        ideal = next_ic.0;
+                        c = next_ic.1;
+                    }
                 }
             }
-        }
+            for h in handles {
+                c = c.union(h.join().expect("gen_con_par thread panicked"));
+            }
+            (ideal, c)
+        }),
         Expr::While { c: body, .. } => {
-            let (i1, c1) = gen_con(body, ideal.clone(), c.clone());
+            let (i1, c1) = gen_con_par(body, ideal.clone(), Cs::new());
             c = c.add(Constraint(ideal.clone(), i1));
             (ideal, c.union(c1))
         }
+        _ => gen_con(e, ideal, c),
     }
 }
 
@@ -163,6 +274,11 @@ pub fn gen_con_less_precise(e: &Expr, mut ideal: PIdeal, mut c: Cs) -> (PIdeal,
             ideal.gens = new_gens;
             (ideal, c)
         }
+        Expr::ParAss { lvs, rvs } => {
+            let map = lvs.iter().cloned().zip(rvs.iter().cloned()).collect();
+            ideal.gens = ideal.gens.iter().map(|tp| tp.subs_many(&map)).collect();
+            (ideal, c)
+        }
         Expr::Skip => (ideal, c),
         Expr::Seq { exprs } => {
             for i in (0..exprs.len()).rev() {
@@ -195,7 +311,7 @@ pub fn gen_con_less_precise(e: &Expr, mut ideal: PIdeal, mut c: Cs) -> (PIdeal,
 }
 
 // Generating Constraints from parser result
-pub fn gen_con_alt(e: &E, mut ideal: PIdeal, mut c: Cs, r: &Rc<RefCell<Ring>>) -> (PIdeal, Cs) {
+pub fn gen_con_alt(e: &E, mut ideal: PIdeal, mut c: Cs, r: &RingRef) -> (PIdeal, Cs) {
     match e {
         E::Ass { v, p } => {
             let v = r.borrow_mut().vextend(v.0.clone());
@@ -207,6 +323,15 @@ pub fn gen_con_alt(e: &E, mut ideal: PIdeal, mut c: Cs, r: &Rc<RefCell<Ring>>) -
             ideal.gens = new_gens;
             (ideal, c)
         }
+        E::ParAss { vs, ps } => {
+            let mut map = HashMap::new();
+            for (v, p) in vs.iter().zip(ps) {
+                let v = r.borrow_mut().vextend(v.0.clone());
+                map.insert(v, create_poly(p, r));
+            }
+            ideal.gens = ideal.gens.iter().map(|tp| tp.subs_many(&map)).collect();
+            (ideal, c)
+        }
         E::Skip => (ideal, c),
         E::Seq { es } => {
             for i in (0..es.len()).rev() {
@@ -265,6 +390,11 @@ pub fn gen_con_alt(e: &E, mut ideal: PIdeal, mut c: Cs, r: &Rc<RefCell<Ring>>) -
 pub struct LinearEquations {
     parsize: usize,
     eqs: HashSet<(LinExp, C)>,
+    names: Vec<String>,
+    // パラメーターによらない非零の定数を 0 にする制約があった
+    contradiction: bool,
+    // 簡約階段形. 一度作れば add_eq で行を足すたびに更新する
+    ech: Option<Echelon>,
 }
 
 impl std::fmt::Display for LinearEquations {
@@ -283,11 +413,11 @@ impl std::fmt::Display for LinearEquations {
                 if outvec[i] == C::zero() {
                     term = String::new();
                 } else if outvec[i] == C::one() {
-                    term = format!("{:?}", Par::new(i));
+                    term = self.names[i].clone();
                 } else if outvec[i] == -C::one() {
-                    term = format!("-{:?}", Par::new(i));
+                    term = format!("-{}", self.names[i]);
                 } else {
-                    term = format!("{}{:?}", outvec[i], Par::new(i));
+                    term = format!("{}{}", outvec[i], self.names[i]);
                 }
                 res = format!("{}{:^6}", res, term);
             }
@@ -300,51 +430,196 @@ impl std::fmt::Display for LinearEquations {
 /*
 */
 
-impl From<(Cs, &Rc<RefCell<Ring>>)> for LinearEquations {
-    fn from(cs_r: (Cs, &Rc<RefCell<Ring>>)) -> Self {
-        let (cs, r) = cs_r;
-        let mut eqs = HashSet::new();
-        for c in cs.items {
-            let (left_pideal, right_pideal) = (c.0, c.1);
-            for t1 in &right_pideal.gens {
-                for t2 in &left_pideal.gens {
-                    // 係数一致
-                    let t = t1.clone() + -t2.clone();
-                    // ゼロにならない
-                    if t.mons
-                        .last()
-                        .expect("mons length 0 at eqs")
-                        .0
-                        .coef
-                        .is_cnst()
-                    {
-                        panic!("solution does'nt exist");
-                    }
+/*
+Simplification
+*/
+// 係数が 0 でない項だけを持つ式 Σ a p = c
+type Row = (BTreeMap<Par, C>, C);
+
+fn drop_empty(rows: &mut Vec<Row>, contradiction: &mut bool) {
+    rows.retain(|(ts, c)| {
+        if ts.is_empty() && !c.is_zero() {
+            *contradiction = true;
+        }
+        !ts.is_empty()
+    });
+}
+
+fn row_of(le: &LinExp) -> BTreeMap<Par, C> {
+    le.terms
+        .iter()
+        .filter(|pt| !pt.coef.is_zero())
+        .filter_map(|pt| pt.par.map(|p| (p, pt.coef)))
+        .collect()
+}
+
+// 行 ts += b row. 0 になった係数は除く
+fn axpy(ts: &mut BTreeMap<Par, C>, b: C, row: &BTreeMap<Par, C>) {
+    for (p, a) in row {
+        let e = ts.entry(*p).or_insert_with(C::zero);
+        *e += b * *a;
+        if e.is_zero() {
+            ts.remove(p);
+        }
+    }
+}
+
+// 簡約階段形. 行は先頭 (番号の一番小さい) パラメーターで引き, 先頭の係数は 1.
+// 先頭のパラメーターは他の行には現れない
+#[derive(PartialEq, Eq, Clone, Debug, Default)]
+pub struct Echelon {
+    rows: BTreeMap<Par, Row>,
+}
+
+impl Echelon {
+    // 足した行が独立だったかと, 0 = c (c ≠ 0) になったかを返す
+    fn add(&mut self, mut ts: BTreeMap<Par, C>, mut c: C) -> (bool, bool) {
+        for (p, (row, rc)) in &self.rows {
+            if let Some(b) = ts.get(p).copied() {
+                axpy(&mut ts, -b, row);
+                c -= b * *rc;
+            }
+        }
+        let (p, a) = match ts.iter().next() {
+            Some((p, a)) => (*p, *a),
+            None => return (false, !c.is_zero()),
+        };
+        for v in ts.values_mut() {
+            *v /= a;
+        }
+        c /= a;
+        for (row, rc) in self.rows.values_mut() {
+            if let Some(b) = row.get(&p).copied() {
+                axpy(row, -b, &ts);
+                *rc -= b * c;
+            }
+        }
+        self.rows.insert(p, (ts, c));
+        (true, false)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Reduction {
+    pub rows_before: usize,
+    pub rows_after: usize,
+    // a p = c の形で定数に決まったパラメーター
+    pub units: usize,
+    // 一つの式にしか現れず, その式ごと消したパラメーター
+    pub defined: usize,
+    // 定数倍で他と重なった式
+    pub duplicates: usize,
+}
 
-                    for m in &t.mons {
-                        let mut le = m.0.coef.clone();
-                        let mut cnst = C::zero();
-                        if le.terms[0].is_cnst() {
-                            cnst = -le.terms[0].coef;
-                            le.terms.remove(0);
-                        }
-                        eqs.insert((le, cnst));
+impl std::fmt::Display for Reduction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} -> {} equations ({} unit, {} defined, {} duplicate)",
+            self.rows_before, self.rows_after, self.units, self.defined, self.duplicates
+        )
+    }
+}
+
+// 制約ごとに, 係数の一致を表す式 Σ a p = c を並べる
+fn cs_eqs(cs: Cs) -> (HashSet<(LinExp, C)>, bool) {
+    let mut eqs = HashSet::new();
+    let mut contradiction = false;
+    for c in cs.items {
+        let (left_pideal, right_pideal) = (c.0, c.1);
+        for t1 in &right_pideal.gens {
+            for t2 in &left_pideal.gens {
+                // 係数一致
+                let t = t1.clone() + -t2.clone();
+                // skip や y = y では差が 0 になり, 制約はない
+                if t.mons.iter().all(|m| m.0.coef.is_zero()) {
+                    continue;
+                }
+                // パラメーターを含まない非零の係数は, どう選んでも 0 にならない
+                if t.mons
+                    .iter()
+                    .any(|m| m.0.coef.is_cnst() && !m.0.coef.is_zero())
+                {
+                    contradiction = true;
+                    continue;
+                }
+
+                for m in &t.mons {
+                    let mut le = m.0.coef.clone();
+                    let mut cnst = C::zero();
+                    if le.terms[0].is_cnst() {
+                        cnst = -le.terms[0].coef;
+                        le.terms.remove(0);
                     }
+                    eqs.insert((le, cnst));
                 }
             }
         }
+    }
+    (eqs, contradiction)
+}
+
+impl From<(Cs, &RingRef)> for LinearEquations {
+    fn from(cs_r: (Cs, &RingRef)) -> Self {
+        let (cs, r) = cs_r;
+        let (eqs, contradiction) = cs_eqs(cs);
+        let r = r.borrow();
+        let parsize = r.par_count();
         LinearEquations {
-            parsize: r.borrow().pars.len(),
+            parsize,
             eqs,
+            names: (0..parsize).map(|i| r.par_name(Par::new(i))).collect(),
+            contradiction,
+            ech: None,
         }
     }
 }
 
 impl LinearEquations {
+    // 不変式の集合が空になる (1 が不変式のイデアルに入る) とき
+    pub fn is_contradictory(&self) -> bool {
+        self.contradiction
+    }
     // TODO: 一部の連立方程式がうまくとけない
     // 求めた解が元の等式を満たすかチェックをする.
     // カーネルの次元を計算する(階段の数と変数の数を見る)
     pub fn solve(&self) -> Option<Vec<(Par, LinExp)>> {
+        self.solve_reduced().0
+    }
+
+    // solve と同じ. 前処理でどれだけ式が減ったかも返す
+    pub fn solve_reduced(&self) -> (Option<Vec<(Par, LinExp)>>, Reduction) {
+        if self.contradiction {
+            return (None, Reduction::default());
+        }
+        let (le, defs, red) = self.simplify();
+        (le.solve_defs(defs), red)
+    }
+
+    // 残った式を解いて, simplify で消したパラメーターを戻す
+    fn solve_defs(&self, defs: Vec<(Par, LinExp)>) -> Option<Vec<(Par, LinExp)>> {
+        let res = self.solve_dense()?;
+        // 消したパラメーターは, 後で消したものから順に解を代入して戻す
+        let mut sol: HashMap<Par, LinExp> = res.into_iter().collect();
+        for (p, d) in defs.iter().rev() {
+            let mut v = LinExp::zero();
+            for pt in &d.terms {
+                match pt.par {
+                    Some(q) => v += sol[&q].clone() * pt.coef,
+                    None => v += LinExp::one() * pt.coef,
+                }
+            }
+            sol.insert(*p, v);
+        }
+        let mut res: Vec<(Par, LinExp)> = sol.into_iter().collect();
+        res.sort_by_key(|e| e.0);
+        Some(res)
+    }
+
+    fn solve_dense(&self) -> Option<Vec<(Par, LinExp)>> {
+        if self.contradiction {
+            return None;
+        }
         // 行列を作る. 縦のインデックスは, setのcollectによせる
         let row_num = std::cmp::max(self.eqs.len(), self.parsize);
         let col_num = self.parsize;
@@ -395,13 +670,6 @@ impl LinearEquations {
         //  Image dimension
         let mut i_dim = cur;
 
-        // Debug
-        for i in 0..row_num {
-            for j in 0..self.parsize {
-                print!("{:^3} ", mat[i][j]);
-            }
-            println!("={}", b[i]);
-        }
         let mut res: Vec<(Par, LinExp)> = (0..self.parsize)
             .rev()
             .map(|i| {
@@ -448,6 +716,169 @@ impl LinearEquations {
         Some(res)
     }
 
+    // 消去の前に, 1 変数の式と 1 つの式にしか現れないパラメーターを取り除き, 定数倍で重なる式をまとめる.
+    // 取り除いたパラメーターは, 残りのパラメーターでの表し方を消した順に返す
+    pub fn simplify(&self) -> (LinearEquations, Vec<(Par, LinExp)>, Reduction) {
+        let mut rows: Vec<Row> = self.eqs.iter().map(|(le, c)| (row_of(le), *c)).collect();
+        let mut red = Reduction {
+            rows_before: rows.len(),
+            ..Reduction::default()
+        };
+        let mut contradiction = self.contradiction;
+        let mut defs = vec![];
+        drop_empty(&mut rows, &mut contradiction);
+        loop {
+            // a p = c なら p を定数にして, 他の式に代入する
+            if let Some(i) = rows.iter().position(|(ts, _)| ts.len() == 1) {
+                let (ts, c) = rows.swap_remove(i);
+                let (p, a) = ts.into_iter().next().expect("unit row");
+                let v = c / a;
+                for (ts, c) in &mut rows {
+                    if let Some(b) = ts.remove(&p) {
+                        *c -= b * v;
+                    }
+                }
+                drop_empty(&mut rows, &mut contradiction);
+                defs.push((p, LinExp::one() * v));
+                red.units += 1;
+                continue;
+            }
+            // 一つの式にしか現れないパラメーターは, その式で決めればよい
+            let mut count: HashMap<Par, usize> = HashMap::new();
+            for (ts, _) in &rows {
+                for p in ts.keys() {
+                    *count.entry(*p).or_insert(0) += 1;
+                }
+            }
+            let single = rows
+                .iter()
+                .enumerate()
+                .find_map(|(i, (ts, _))| ts.keys().find(|p| count[p] == 1).map(|p| (i, *p)));
+            if let Some((i, p)) = single {
+                let (ts, c) = rows.swap_remove(i);
+                let a = ts[&p];
+                let mut d = LinExp::one() * (c / a);
+                for (q, b) in &ts {
+                    if *q != p {
+                        d += LinExp::from(*q) * (-*b / a);
+                    }
+                }
+                defs.push((p, d));
+                red.defined += 1;
+                continue;
+            }
+            break;
+        }
+        // 先頭の係数を 1 にそろえてから重複を除く
+        let mut eqs = HashSet::new();
+        for (ts, c) in rows {
+            let a = *ts.values().next().expect("non-empty row");
+            let le = LinExp::from(
+                ts.iter()
+                    .map(|(p, b)| ParTerm::from(*p) * (*b / a))
+                    .collect::<Vec<ParTerm>>(),
+            );
+            if !eqs.insert((le, c / a)) {
+                red.duplicates += 1;
+            }
+        }
+        red.rows_after = eqs.len();
+        let le = LinearEquations {
+            parsize: self.parsize,
+            eqs,
+            names: self.names.clone(),
+            contradiction,
+            ech: None,
+        };
+        (le, defs, red)
+    }
+
+    // 階段形がなければ, 今ある式から作る
+    fn echelon(&mut self) -> &mut Echelon {
+        if self.ech.is_none() {
+            let mut ech = Echelon::default();
+            for (le, c) in &self.eqs {
+                let (_, contra) = ech.add(row_of(le), *c);
+                self.contradiction |= contra;
+            }
+            self.ech = Some(ech);
+        }
+        self.ech.as_mut().expect("echelon form")
+    }
+
+    // 行を一つ足す. 階段形の上でその場で消去して, 解の空間が狭まったかを返す
+    pub fn add_eq(&mut self, le: LinExp, c: C) -> bool {
+        for p in le.terms.iter().filter_map(|pt| pt.par) {
+            while self.parsize <= p.id {
+                self.names.push(format!("{:?}", Par::new(self.parsize)));
+                self.parsize += 1;
+            }
+        }
+        if self.eqs.contains(&(le.clone(), c)) {
+            return false;
+        }
+        // 足す行より先に, 今ある式だけで階段形を作っておく
+        let (indep, contra) = self.echelon().add(row_of(&le), c);
+        self.contradiction |= contra;
+        self.eqs.insert((le, c));
+        indep
+    }
+
+    // 別の解析で増えた制約をまとめて足す. 独立だった行の数を返す
+    pub fn extend(&mut self, cs: Cs, r: &RingRef) -> usize {
+        let (eqs, contradiction) = cs_eqs(cs);
+        self.contradiction |= contradiction;
+        {
+            let r = r.borrow();
+            while self.parsize < r.par_count() {
+                self.names.push(r.par_name(Par::new(self.parsize)));
+                self.parsize += 1;
+            }
+        }
+        eqs.into_iter()
+            .filter(|(le, c)| self.add_eq(le.clone(), *c))
+            .count()
+    }
+
+    // 階段形を (なければ作って) 使って解く. 自由なパラメーターの名前の付け方は solve と同じ
+    pub fn solve_incremental(&mut self) -> Option<Vec<(Par, LinExp)>> {
+        self.echelon();
+        if self.contradiction {
+            return None;
+        }
+        let ech = self.ech.as_ref().expect("echelon form");
+        let n = self.parsize;
+        let free = |p: Par| LinExp::from(Par::new(2 * n - 1 - p.id));
+        let res = (0..n)
+            .map(Par::new)
+            .map(|p| match ech.rows.get(&p) {
+                Some((ts, c)) => {
+                    let mut v = LinExp::one() * *c;
+                    for (q, b) in ts.iter().filter(|(q, _)| **q != p) {
+                        v += free(*q) * -*b;
+                    }
+                    (p, v)
+                }
+                None => (p, free(p)),
+            })
+            .collect();
+        Some(res)
+    }
+
+    pub fn rows(&self) -> Vec<(LinExp, C)> {
+        self.eqs.iter().cloned().collect()
+    }
+    pub fn num_eqs(&self) -> usize {
+        self.eqs.len()
+    }
+    pub fn num_pars(&self) -> usize {
+        self.parsize
+    }
+    // 階段形の行の数. まだ作っていなければ None
+    pub fn rank(&self) -> Option<usize> {
+        self.ech.as_ref().map(|e| e.rows.len())
+    }
+
     pub fn check(&self, sol: &Vec<(Par, LinExp)>) {
         use std::collections::HashMap;
         let sol_map = sol.clone().into_iter().collect::<HashMap<Par, LinExp>>();
@@ -528,7 +959,7 @@ fn zero_and_mostgen() {
         }
         None => {
             println!("Solution dosn't exist");
-            std::process::exit(0);
+            return;
         }
     }
     println!("{:?}", pars);
@@ -544,3 +975,350 @@ fn zero_and_mostgen() {
         println!("{:?}", inv.clone().subs_pars(e.clone()));
     }
 }
+
+#[test]
+fn gen_con_par_matches_gen_con() {
+    use super::p_comb::Parser;
+    let src = r#"
+        y1 = 0; y2 = 0; y3 = x1;
+        while (y3 != 0) {
+            if (y2 + 1 == x2) {
+                y1 = y1 + 1;
+                y2 = 0;
+                y3 = y3 - 1;
+            } else {
+                y2 = y2 + 1;
+                y3 = y3 - 1;
+            }
+        }
+        z = 0;
+        while (z - y1 != 0) {
+            z = z + 1;
+        }
+    "#;
+    let run = |par: bool| {
+        let r = Ring::new();
+        let e = program().parse(src).map_or(E::Skip, |(_i, c)| c);
+        let e = convert_from_parseresult(&e, &r);
+        let g = Temp::most_gen(2, &r);
+        let (i, c) = if par {
+            gen_con_par(&e, PIdeal::from(g.clone()), Cs::new())
+        } else {
+            gen_con(&e, PIdeal::from(g.clone()), Cs::new())
+        };
+        let c = c.add(Constraint(i, PIdeal::zero(&r)));
+        let n_cons = c.items.len();
+        let le = LinearEquations::from((c, &r));
+        let sol = le.solve().expect("solution");
+        le.check(&sol);
+        let n_pars = r.borrow().par_count();
+        (n_cons, n_pars, g.subs_pars(sol).get_pars().len())
+    };
+    // パラメーターの番号は違っても, 制約の数と解空間の次元は同じ
+    assert!(run(false) == run(true));
+}
+
+#[test]
+fn zero_guard() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let e = program()
+        .parse(
+            r#"
+            x = 0; y = 0;
+            while (x - n != 0) {
+                if (y - y == 0) { y = y + 1; } else { y = y + 2; }
+                x = x + 1;
+            }"#,
+        )
+        .map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let v = |s: &str| Poly::from((r.borrow().var_by_name(s).unwrap(), &r));
+    let _scope = r.par_scope();
+    for mode in [RemMode::Quotient, RemMode::NormalForm].iter() {
+        let g = Temp::most_gen(1, &r);
+        let (i, c) = gen_con_with(
+            &e,
+            PIdeal::from(g.clone()),
+            Cs::new(),
+            *mode,
+            &mut GenStats::default(),
+        );
+        let c = c.add(Constraint(i, PIdeal::zero(&r)));
+        let mut le = LinearEquations::from((c, &r));
+        let sol = le.solve_incremental().expect("solution");
+        let invs = g.subs_pars(sol).components();
+        println!("{:?}", invs);
+        // いつも then 側なので y = x
+        assert!(super::ideal::Ideal::new(invs, &r)
+            .contains(&(v("y") - v("x")))
+            .unwrap());
+    }
+}
+
+#[test]
+fn rem_par_pars_released_per_analysis() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let e = program()
+        .parse(
+            r#"
+            y1 = 0; y2 = 0; y3 = x1;
+            while (y3 != 0) {
+                if (y2 + 1 == x2) { y1 = y1 + 1; y2 = 0; y3 = y3 - 1; }
+                else { y2 = y2 + 1; y3 = y3 - 1; }
+            }"#,
+        )
+        .map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let g = Temp::most_gen(2, &r);
+    let n_template = r.borrow().par_count();
+    for (i, p) in g.get_pars().into_iter().enumerate() {
+        r.borrow_mut().name_par(p, format!("c{}", i));
+    }
+    let mut sizes = vec![];
+    for _ in 0..2 {
+        // 商のパラメーターは解析ごとに確保して捨てる
+        let _scope = r.par_scope();
+        let (i, c) = gen_con(&e, PIdeal::from(g.clone()), Cs::new());
+        let c = c.add(Constraint(i, PIdeal::zero(&r)));
+        let le = LinearEquations::from((c, &r));
+        assert!(format!("{}", le).contains("c0"));
+        assert!(le.solve().is_some());
+        sizes.push(r.borrow().par_count());
+    }
+    assert!(sizes[0] == sizes[1] && sizes[0] > n_template);
+    assert!(r.borrow().par_count() == n_template);
+    assert!(format!("{:?}", g).contains("c1"));
+}
+
+#[test]
+fn par_ass_swaps() {
+    use super::p_comb::Parser;
+    let src = r#"
+        x = 1; y = 2;
+        while (x - y != 0) {
+            x, y = y, x;
+        }
+    "#;
+    let r = Ring::new();
+    let e = program().parse(src).map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let (x, y) = (
+        r.borrow().var_by_name("x").unwrap(),
+        r.borrow().var_by_name("y").unwrap(),
+    );
+    let g = Temp::most_gen(2, &r);
+    let (i, c) = gen_con(&e, PIdeal::from(g.clone()), Cs::new());
+    let c = c.add(Constraint(i, PIdeal::zero(&r)));
+    let le = LinearEquations::from((c, &r));
+    let sol = le.solve().expect("solution");
+    le.check(&sol);
+    let inv = g.subs_pars(sol);
+    println!("{:?}", inv);
+    // 入れ替えた後の点 (2, 1) でも消える
+    for (a, b) in [(1, 2), (2, 1)] {
+        let pt: HashMap<Var, C> = vec![(x, C::new(a, 1)), (y, C::new(b, 1))]
+            .into_iter()
+            .collect();
+        assert!(inv.eval_vars(&pt).is_zero());
+    }
+}
+
+#[test]
+fn contradictory_constraints() {
+    let r = Ring::new();
+    r.borrow_mut().vextend("x".to_string());
+    // 1 が 0 のイデアルに入るという制約は, どのパラメーターでも満たせない
+    let one = Temp::from((vec![Mon::<LinExp>::one(&r)], &r));
+    let c = Cs::new().add(Constraint(PIdeal::from(one), PIdeal::zero(&r)));
+    let le = LinearEquations::from((c, &r));
+    assert!(le.is_contradictory());
+    assert!(le.solve().is_none());
+    // 本体で何も変わらないループは, 差が 0 になるだけで矛盾ではない
+    use super::p_comb::Parser;
+    for body in ["skip;", "y = y;"] {
+        let r = Ring::new();
+        let src = format!("x = 0; while (x - 10 != 0) {{ {} }}", body);
+        let e = program().parse(&src).map_or(E::Skip, |(_i, c)| c);
+        let e = convert_from_parseresult(&e, &r);
+        let g = Temp::most_gen(1, &r);
+        let (i, c) = gen_con(&e, PIdeal::from(g.clone()), Cs::new());
+        let c = c.add(Constraint(i, PIdeal::zero(&r)));
+        let le = LinearEquations::from((c, &r));
+        assert!(!le.is_contradictory());
+        let sol = le.solve().expect("solution");
+        le.check(&sol);
+        // x = 0 が見つかる
+        let invs = g.subs_pars(sol).components();
+        println!("{}: {:?}", body, invs);
+        let x = Poly::from((r.borrow().var_by_name("x").unwrap(), &r));
+        let found = super::ideal::Ideal::new(invs, &r);
+        assert!(found.contains(&x).unwrap());
+    }
+}
+
+#[test]
+fn gen_con_normal_form_mode() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let e = program()
+        .parse(
+            r#"
+            y1 = 0; y2 = 0; y3 = x1;
+            while (y3 != 0) {
+                if (y2 + 1 == x2) { y1 = y1 + 1; y2 = 0; y3 = y3 - 1; }
+                else { y2 = y2 + 1; y3 = y3 - 1; }
+            }"#,
+        )
+        .map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let v = |s: &str| r.borrow().var_by_name(s).unwrap();
+    let g = Temp::most_gen(2, &r);
+    let mut run = |mode| {
+        let _scope = r.par_scope();
+        let mut stats = GenStats::default();
+        let (i, c) = gen_con_with(&e, PIdeal::from(g.clone()), Cs::new(), mode, &mut stats);
+        let c = c.add(Constraint(i, PIdeal::zero(&r)));
+        let le = LinearEquations::from((c, &r));
+        let sol = le.solve().expect("solution");
+        le.check(&sol);
+        (stats, g.subs_pars(sol))
+    };
+    let (qs, _) = run(RemMode::Quotient);
+    let (ns, inv) = run(RemMode::NormalForm);
+    println!("{:?} {:?}", qs, ns);
+    assert!(qs.divisions == 1 && qs.fresh > 0);
+    assert!(ns.divisions == 1 && ns.fresh == 0);
+    // 正規形でも y1 x2 + y2 + y3 - x1 = 0 は残る
+    for (x1, x2, y1, y2, y3) in [(7, 3, 0, 0, 7), (7, 3, 1, 1, 3), (7, 3, 2, 1, 0)] {
+        let pt: HashMap<Var, C> = vec![
+            (v("x1"), C::new(x1, 1)),
+            (v("x2"), C::new(x2, 1)),
+            (v("y1"), C::new(y1, 1)),
+            (v("y2"), C::new(y2, 1)),
+            (v("y3"), C::new(y3, 1)),
+        ]
+        .into_iter()
+        .collect();
+        assert!(inv.eval_vars(&pt).is_zero());
+    }
+}
+
+#[test]
+fn simplify_equations() {
+    let a = |i: usize, c: i64| LinExp::from(Par::new(i)) * C::new(c, 1);
+    let eqs: HashSet<(LinExp, C)> = vec![
+        (a(0, 1), C::new(2, 1)),
+        (a(0, 1) + a(1, 1) + a(2, 1), C::new(5, 1)),
+        (a(1, 2) + a(2, 2), C::new(6, 1)),
+        (a(1, 1) + a(3, -1), C::zero()),
+        (a(1, 3) + a(3, -3), C::zero()),
+        (a(3, 1) + a(4, 1), C::one()),
+    ]
+    .into_iter()
+    .collect();
+    let le = LinearEquations {
+        parsize: 5,
+        eqs,
+        names: (0..5).map(|i| format!("a{}", i)).collect(),
+        contradiction: false,
+        ech: None,
+    };
+    let (small, defs, red) = le.simplify();
+    println!("{}", red);
+    println!("{}", small);
+    assert!(
+        red == Reduction {
+            rows_before: 6,
+            rows_after: 2,
+            units: 1,
+            defined: 1,
+            duplicates: 2,
+        }
+    );
+    assert!(defs[0] == (Par::new(0), LinExp::one() * C::new(2, 1)));
+    // 消す前の式も満たす
+    let (sol, red2) = le.solve_reduced();
+    let sol = sol.expect("solution");
+    assert!(red2 == red);
+    le.check(&sol);
+    assert!(sol[0].1 == LinExp::one() * C::new(2, 1));
+    // 残る自由度は 1 つ
+    let mut free: Vec<Par> = sol
+        .iter()
+        .flat_map(|(_, l)| l.terms.iter().filter_map(|pt| pt.par))
+        .collect();
+    free.sort();
+    free.dedup();
+    assert!(free.len() == 1);
+    // 消した結果 0 = 1 になる
+    let mut eqs = HashSet::new();
+    eqs.insert((a(0, 1), C::one()));
+    eqs.insert((a(0, 2), C::one()));
+    let le = LinearEquations {
+        parsize: 1,
+        eqs,
+        names: vec!["a0".to_string()],
+        contradiction: false,
+        ech: None,
+    };
+    assert!(le.simplify().0.is_contradictory());
+    assert!(le.solve().is_none());
+}
+
+#[test]
+fn incremental_solving() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let parse = |src: &str| {
+        let e = program().parse(src).map_or(E::Skip, |(_i, c)| c);
+        convert_from_parseresult(&e, &r)
+    };
+    let e1 = parse("x = 0; y = 0; while (x - n != 0) { x = x + 1; y = y + 2; }");
+    let e2 = parse("x = 0; y = 1; while (x - n != 0) { x = x + 1; y = y + 2; }");
+    let g = Temp::most_gen(1, &r);
+    let cs = |e: &Expr| {
+        let (i, c) = gen_con(e, PIdeal::from(g.clone()), Cs::new());
+        c.add(Constraint(i, PIdeal::zero(&r)))
+    };
+    let free = |sol: &Vec<(Par, LinExp)>| {
+        let mut ps: Vec<Par> = sol
+            .iter()
+            .flat_map(|(_, l)| l.terms.iter().filter_map(|pt| pt.par))
+            .collect();
+        ps.sort();
+        ps.dedup();
+        ps.len()
+    };
+    // 階段形を作る前でも, 従属な行は数えない
+    let (i, c) = gen_con(&e1, PIdeal::from(g.clone()), Cs::new());
+    let mut fresh = LinearEquations::from((c.add(Constraint(i.clone(), PIdeal::zero(&r))), &r));
+    assert!(fresh.rank().is_none());
+    let twice = i.mul(&Poly::from((C::new(2, 1), &r)));
+    assert!(fresh.extend(Cs::new().add(Constraint(twice, PIdeal::zero(&r))), &r) == 0);
+    let mut le = LinearEquations::from((cs(&e1), &r));
+    assert!(le.rank().is_none());
+    let sol = le.solve_incremental().expect("solution");
+    le.check(&sol);
+    // 消去の結果は solve と同じ
+    assert!(free(&sol) == free(&le.solve().expect("solution")));
+    let rank = le.rank().unwrap();
+    // 同じ式は足しても変わらない
+    let (l, c) = le.eqs.iter().next().cloned().unwrap();
+    assert!(!le.add_eq(l, c));
+    // 初期値を変えた版の制約も満たす不変式だけ残る. y = 2x は消える
+    assert!(le.extend(cs(&e2), &r) > 0);
+    assert!(le.rank().unwrap() > rank);
+    let sol2 = le.solve_incremental().expect("solution");
+    le.check(&sol2);
+    assert!(free(&sol2) < free(&sol));
+    let inv = g.subs_pars(sol2);
+    println!("{:?}", inv);
+    // 両立しない式を足すと解がなくなる
+    let p = Par::new(0);
+    le.add_eq(LinExp::from(p), C::one());
+    le.add_eq(LinExp::from(p), C::new(2, 1));
+    assert!(le.is_contradictory());
+    assert!(le.solve_incremental().is_none());
+}
@@ -6,8 +6,7 @@ use super::p_comb::*;
 use super::poly::*;
 use super::poly_parse::*;
 use super::ring::*;
-use std::cell::RefCell;
-use std::rc::Rc;
+use super::temp::*;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Pred {
@@ -27,6 +26,11 @@ pub enum Expr {
         lv: Var,
         rv: Poly,
     },
+    // 並行代入. lvs[i] に rvs[i] を同時に代入する
+    ParAss {
+        lvs: Vec<Var>,
+        rvs: Vec<Poly>,
+    },
     Skip,
     Seq {
         exprs: Vec<Expr>,
@@ -117,14 +121,38 @@ fn mannadiv_simple() {
         exprs: vec![c_init, c_if],
     };
 }
+// ソースの //@ template 注釈から作るテンプレート. 各式に現れる単項式を全部使う
+pub fn template_from_source(src: &str, r: &RingRef) -> Option<Temp> {
+    let ps = find_template(src)?;
+    let mut ms = vec![];
+    for p in &ps {
+        let p = create_poly(p, r);
+        ms.extend(
+            p.mons
+                .into_iter()
+                .filter(|m| !m.0.coef.is_zero())
+                .map(|m| m.0.vars),
+        );
+    }
+    Some(Temp::from_mons(&ms, r))
+}
+
 // 環に変数を追加しながら, 都合の良い形に変換する.
-pub fn convert_from_parseresult(e: &E, r: &Rc<RefCell<Ring>>) -> Expr {
+pub fn convert_from_parseresult(e: &E, r: &RingRef) -> Expr {
     match e {
         E::Ass { v, p } => {
             let v = r.borrow_mut().vextend(v.0.clone());
             let p = create_poly(p, r);
             Expr::Ass { lv: v, rv: p }
         }
+        E::ParAss { vs, ps } => {
+            let lvs = vs
+                .iter()
+                .map(|v| r.borrow_mut().vextend(v.0.clone()))
+                .collect();
+            let rvs = ps.iter().map(|p| create_poly(p, r)).collect();
+            Expr::ParAss { lvs, rvs }
+        }
         E::Skip => Expr::Skip,
         E::Seq { es } => {
             let mut exprs = vec![];
@@ -145,9 +173,45 @@ pub fn convert_from_parseresult(e: &E, r: &Rc<RefCell<Ring>>) -> Expr {
                 els: Box::new(e),
             }
         }
-        E::While { guard, body } => Expr::While {
+        E::While { guard, body, .. } => Expr::While {
             guard: Pred::new(create_poly(&guard.p, r), guard.eq),
             c: Box::new(convert_from_parseresult(body, r)),
         },
     }
 }
+
+#[test]
+fn template_from_annotation() {
+    let src = r#"
+        //@ template y - x*x, x, 1
+        x = 0; y = 0;
+        while (x - n != 0) {
+            y = y + 2 * x + 1;
+            x = x + 1;
+        }
+    "#;
+    let r = Ring::new();
+    let e = program().parse(src).map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let g = template_from_source(src, &r).expect("annotation");
+    println!("{:?}", g);
+    // y, x^2, x, 1 の四つ
+    assert!(g.mons.len() == 4 && g.get_pars().len() == 4);
+    let (i, c) = gen_con(&e, PIdeal::from(g.clone()), Cs::new());
+    let c = c.add(Constraint(i, PIdeal::zero(&r)));
+    let le = LinearEquations::from((c, &r));
+    let sol = le.solve().expect("solution");
+    le.check(&sol);
+    let inv = g.subs_pars(sol);
+    println!("{:?}", inv);
+    // y - x^2 の定数倍
+    assert!(!inv.get_pars().is_empty());
+    let x = r.borrow().var_by_name("x").unwrap();
+    let y = r.borrow().var_by_name("y").unwrap();
+    for (a, b) in [(0, 0), (3, 9), (-2, 4)] {
+        let pt: std::collections::HashMap<Var, C> = vec![(x, C::new(a, 1)), (y, C::new(b, 1))]
+            .into_iter()
+            .collect();
+        assert!(inv.eval_vars(&pt).is_zero());
+    }
+}
@@ -7,12 +7,14 @@ use super::ring::*;
 
 // BNF
 // program := expr*;
-// expr := assign ';' | if_stmt | while_stmt | "skip" ';';
+// expr := assign ';' | par_assign ';' | if_stmt | while_stmt | "skip" ';';
 // assign := var '=' poly;
+// par_assign := var (',' var)+ '=' poly (',' poly)+;
 // if_stmt := "if" '(' pred ')' '{' stmt* '}' ("else" '{' stmt* '}')?;
 // while_stmt := "while" '(' pred ')' '{' stmt* '}';
 // pred := poly ('==' | '!=') poly;
 // var := identifier
+// '//' から行末まで, '/*' から '*/' まではコメント. "//@ template" poly (',' poly)* はテンプレートの注釈
 // やっぱり変数をRingに追加しながら構文解析みたいなのきついな...
 // でも変数をStringにするのはやばそうだから, 一旦また別のEnumかませる
 // というか, 評価する対象をこれにすればいいだけでは.
@@ -31,6 +33,11 @@ pub enum E {
         v: V,
         p: P,
     },
+    // x, y = y, x; 右辺は全部代入前の値で評価する
+    ParAss {
+        vs: Vec<V>,
+        ps: Vec<P>,
+    },
     Skip,
     Seq {
         es: Vec<E>,
@@ -43,6 +50,8 @@ pub enum E {
     While {
         guard: Pre,
         body: Box<E>,
+        // "while" の位置. ソースの残りの長さで持つ
+        at: usize,
     },
 }
 
@@ -82,6 +91,50 @@ fn assign_parser() {
     assert_eq!(Ok(("", expected)), assign().parse("y4 = 7"));
 }
 
+// 左辺と右辺の個数が合わないものや, 同じ変数に二回代入するものは弾く
+fn par_assign<'a>() -> impl Parser<'a, E> {
+    let vars = pair(
+        whitespace_wrap(var()),
+        one_or_more(right(match_literal(","), whitespace_wrap(var()))),
+    );
+    let polys = pair(
+        whitespace_wrap(poly()),
+        one_or_more(right(match_literal(","), whitespace_wrap(poly()))),
+    );
+    pair(left(vars, match_literal("=")), polys)
+        .map(|((v, mut vs), (p, mut ps))| {
+            vs.insert(0, v);
+            ps.insert(0, p);
+            (vs, ps)
+        })
+        .pred(|(vs, ps)| vs.len() == ps.len() && (0..vs.len()).all(|i| !vs[..i].contains(&vs[i])))
+        .map(|(vs, ps)| E::ParAss { vs, ps })
+}
+
+#[test]
+fn par_assign_parser() {
+    let expected = E::ParAss {
+        vs: vec![V("x".to_string()), V("y".to_string())],
+        ps: vec![P::Var("y".to_string()), P::Var("x".to_string())],
+    };
+    assert_eq!(Ok(("", expected)), par_assign().parse("x, y = y, x"));
+    assert!(par_assign().parse("x, y = y").is_err());
+    assert!(par_assign().parse("x, x = 1, 2").is_err());
+    let expected = E::Seq {
+        es: vec![
+            E::ParAss {
+                vs: vec![V("x".to_string()), V("y".to_string())],
+                ps: vec![P::Var("y".to_string()), P::Num(1)],
+            },
+            E::Ass {
+                v: V("z".to_string()),
+                p: P::Var("x".to_string()),
+            },
+        ],
+    };
+    assert_eq!(Ok(("", expected)), program().parse("x, y = y, 1; z = x;"));
+}
+
 fn pred<'a>() -> impl Parser<'a, Pre> {
     pair(
         pair(
@@ -240,14 +293,35 @@ fn while_cnd<'a>() -> impl Parser<'a, Pre> {
 }
 
 fn while_stmt<'a>() -> impl Parser<'a, E> {
-    while_cnd().and_then(|pred| {
+    pair(right(skip0(), rest_len()), while_cnd()).and_then(|(at, pred)| {
         nested_program().map(move |c| E::While {
             guard: pred.clone(),
             body: Box::new(c),
+            at,
         })
     })
 }
 
+// ソースの先頭からの "while" の位置. 先行順
+pub fn loop_offsets(e: &E, src: &str) -> Vec<usize> {
+    match e {
+        E::Seq { es } => es.iter().flat_map(|e| loop_offsets(e, src)).collect(),
+        E::If { the, els, .. } => {
+            let mut res = loop_offsets(the, src);
+            if let Some(els) = els {
+                res.extend(loop_offsets(els, src));
+            }
+            res
+        }
+        E::While { body, at, .. } => {
+            let mut res = vec![src.len() - at];
+            res.extend(loop_offsets(body, src));
+            res
+        }
+        _ => vec![],
+    }
+}
+
 #[test]
 fn while_parser() {
     let expected = E::While {
@@ -262,11 +336,15 @@ fn while_parser() {
             v: V("x1".to_string()),
             p: P::Num(0),
         }),
+        at: 26,
     };
     assert_eq!(
         Ok(("", expected)),
         while_stmt().parse("while (0 == 0) { x1 = 0; }")
     );
+    let src = "x = 0;\n  while (x != 1) { while (y == 0) { y = 1; } }";
+    let (_, e) = program().parse(src).unwrap();
+    assert_eq!(loop_offsets(&e, src), vec![9, 26]);
 }
 
 fn skip<'a>() -> impl Parser<'a, E> {
@@ -276,12 +354,69 @@ fn expr<'a>() -> impl Parser<'a, E> {
     either(
         left(assign(), match_literal(";")),
         either(
-            if_stmt(),
-            either(while_stmt(), left(skip(), match_literal(";"))),
+            left(par_assign(), match_literal(";")),
+            either(
+                if_stmt(),
+                either(while_stmt(), left(skip(), match_literal(";"))),
+            ),
         ),
     )
 }
 
+// //@ template x*y, x, y, 1
+fn template_annotation<'a>() -> impl Parser<'a, Vec<P>> {
+    let polys = pair(
+        whitespace_wrap(poly()),
+        zero_or_more(right(match_literal(","), whitespace_wrap(poly()))),
+    );
+    right(
+        pair(
+            match_literal("//@"),
+            whitespace_wrap(match_literal("template")),
+        ),
+        polys,
+    )
+    .map(|(p, mut ps)| {
+        ps.insert(0, p);
+        ps
+    })
+}
+
+// ソース中の最初のテンプレート注釈の単項式
+pub fn find_template(src: &str) -> Option<Vec<P>> {
+    src.lines()
+        .map(|l| l.trim())
+        .filter(|l| l.starts_with("//@"))
+        .find_map(|l| match template_annotation().parse(l) {
+            Ok(("", ps)) => Some(ps),
+            _ => None,
+        })
+}
+
+#[test]
+fn template_annotation_parser() {
+    let src = r#"
+        x = 0; // 初期化
+        //@ template x*y, x, y, 1
+        while (x - n != 0) { x = x + 1; }
+    "#;
+    let expected = vec![
+        P::Mul {
+            exp1: Box::new(P::Var("x".to_string())),
+            exp2: Box::new(P::Var("y".to_string())),
+        },
+        P::Var("x".to_string()),
+        P::Var("y".to_string()),
+        P::Num(1),
+    ];
+    assert_eq!(find_template(src), Some(expected));
+    assert_eq!(find_template("x = 0;"), None);
+    // コメントは読み飛ばす
+    let (rest, e) = program().parse(src).expect("program with comments");
+    assert_eq!(rest, "");
+    assert!(matches!(e, E::Seq { es } if es.len() == 2));
+}
+
 pub fn program<'a>() -> impl Parser<'a, E> {
     zero_or_more(whitespace_wrap(expr())).map(move |es| {
         if es.len() == 0 {
@@ -358,6 +493,7 @@ fn p_program_parser() {
             the: Box::new(c_then),
             els: Some(Box::new(c_else)),
         }),
+        at: 298,
     };
     let expected = E::Seq {
         es: vec![
@@ -397,5 +533,5 @@ fn p_program_parser() {
     );
     // 変数一覧を取らなきゃいけなかった.
     let r = Ring::new();
-    // gen_con_alt(&expected, PIdeal::most_gen(d: usize, r: &Rc<RefCell<Ring>>), mut c: Cs, r: &Rc<RefCell<Ring>>)
+    // gen_con_alt(&expected, PIdeal::most_gen(d: usize, r: &RingRef), mut c: Cs, r: &RingRef)
 }
@@ -0,0 +1,799 @@
+use super::coef::*;
+use super::mon::*;
+use super::poly::*;
+use super::ring::*;
+use super::temp::*;
+use itertools::Itertools;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+// 無平方分解してから, 各部分を Kronecker 代入で一変数に落として整数上で因数分解し,
+// 一変数の因子の組み合わせを元に戻して割り切れるか試す.
+// 一変数は Zassenhaus の方法. mod p で Cantor-Zassenhaus, Hensel 持ち上げ, 組み合わせの試し割り
+
+/*
+Polynomials over Z/pZ
+*/
+// 係数は [0, p) で, 低い次数から並べる. p < 2^31 なので積は u64 に収まる
+
+type UPoly = Vec<u64>;
+
+fn trim(f: &mut UPoly) {
+    while f.last() == Some(&0) {
+        f.pop();
+    }
+}
+
+fn inv_mod(a: u64, p: u64) -> u64 {
+    pow_mod(a, p - 2, p)
+}
+
+fn pow_mod(mut a: u64, mut e: u64, p: u64) -> u64 {
+    let mut res = 1;
+    a %= p;
+    while e > 0 {
+        if e & 1 == 1 {
+            res = res * a % p;
+        }
+        a = a * a % p;
+        e >>= 1;
+    }
+    res
+}
+
+fn p_sub(f: &UPoly, g: &UPoly, p: u64) -> UPoly {
+    let mut res = vec![0; f.len().max(g.len())];
+    for (i, c) in f.iter().enumerate() {
+        res[i] = *c;
+    }
+    for (i, c) in g.iter().enumerate() {
+        res[i] = (res[i] + p - c) % p;
+    }
+    trim(&mut res);
+    res
+}
+
+fn p_add(f: &UPoly, g: &UPoly, p: u64) -> UPoly {
+    let mut res = vec![0; f.len().max(g.len())];
+    for (i, c) in f.iter().enumerate() {
+        res[i] = *c;
+    }
+    for (i, c) in g.iter().enumerate() {
+        res[i] = (res[i] + c) % p;
+    }
+    trim(&mut res);
+    res
+}
+
+fn p_mul(f: &UPoly, g: &UPoly, p: u64) -> UPoly {
+    if f.is_empty() || g.is_empty() {
+        return vec![];
+    }
+    let mut res = vec![0; f.len() + g.len() - 1];
+    for (i, a) in f.iter().enumerate() {
+        if *a == 0 {
+            continue;
+        }
+        for (j, b) in g.iter().enumerate() {
+            res[i + j] = (res[i + j] + a * b) % p;
+        }
+    }
+    trim(&mut res);
+    res
+}
+
+fn p_scale(f: &UPoly, c: u64, p: u64) -> UPoly {
+    let mut res: UPoly = f.iter().map(|a| a * c % p).collect();
+    trim(&mut res);
+    res
+}
+
+fn p_divrem(f: &UPoly, g: &UPoly, p: u64) -> (UPoly, UPoly) {
+    assert!(!g.is_empty(), "division by zero polynomial mod p");
+    let mut r = f.clone();
+    if r.len() < g.len() {
+        return (vec![], r);
+    }
+    let li = inv_mod(*g.last().unwrap(), p);
+    let mut q = vec![0; r.len() - g.len() + 1];
+    while r.len() >= g.len() {
+        let k = r.len() - g.len();
+        let c = r.last().unwrap() * li % p;
+        q[k] = c;
+        for (j, b) in g.iter().enumerate() {
+            r[k + j] = (r[k + j] + p - c * b % p) % p;
+        }
+        trim(&mut r);
+    }
+    trim(&mut q);
+    (q, r)
+}
+
+fn p_monic(f: &UPoly, p: u64) -> UPoly {
+    match f.last() {
+        Some(c) => p_scale(f, inv_mod(*c, p), p),
+        None => vec![],
+    }
+}
+
+fn p_gcd(f: &UPoly, g: &UPoly, p: u64) -> UPoly {
+    let (mut a, mut b) = (f.clone(), g.clone());
+    while !b.is_empty() {
+        let r = p_divrem(&a, &b, p).1;
+        a = b;
+        b = r;
+    }
+    p_monic(&a, p)
+}
+
+// s f + t g = gcd (monic)
+fn p_ext_gcd(f: &UPoly, g: &UPoly, p: u64) -> (UPoly, UPoly, UPoly) {
+    let (mut r0, mut r1) = (f.clone(), g.clone());
+    let (mut s0, mut s1) = (vec![1], vec![]);
+    let (mut t0, mut t1) = (vec![], vec![1]);
+    while !r1.is_empty() {
+        let (q, r) = p_divrem(&r0, &r1, p);
+        let s = p_sub(&s0, &p_mul(&q, &s1, p), p);
+        let t = p_sub(&t0, &p_mul(&q, &t1, p), p);
+        r0 = std::mem::replace(&mut r1, r);
+        s0 = std::mem::replace(&mut s1, s);
+        t0 = std::mem::replace(&mut t1, t);
+    }
+    let li = inv_mod(*r0.last().expect("gcd of zero polynomials"), p);
+    (
+        p_scale(&r0, li, p),
+        p_scale(&s0, li, p),
+        p_scale(&t0, li, p),
+    )
+}
+
+fn p_powmod(f: &UPoly, mut e: u64, m: &UPoly, p: u64) -> UPoly {
+    let mut res = vec![1];
+    let mut base = p_divrem(f, m, p).1;
+    while e > 0 {
+        if e & 1 == 1 {
+            res = p_divrem(&p_mul(&res, &base, p), m, p).1;
+        }
+        base = p_divrem(&p_mul(&base, &base, p), m, p).1;
+        e >>= 1;
+    }
+    res
+}
+
+fn p_diff(f: &UPoly, p: u64) -> UPoly {
+    let mut res: UPoly = f
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, c)| (i as u64 % p) * c % p)
+        .collect();
+    trim(&mut res);
+    res
+}
+
+// 係数の決まった乱数で十分
+struct XorShift(u64);
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+// 次数ごとに分ける. f は monic で無平方
+fn distinct_degree(mut f: UPoly, p: u64) -> Vec<(UPoly, usize)> {
+    let mut res = vec![];
+    let x = vec![0, 1];
+    let mut h = x.clone();
+    let mut d = 1;
+    while f.len() > 2 * d {
+        h = p_powmod(&h, p, &f, p);
+        let g = p_gcd(&p_sub(&h, &x, p), &f, p);
+        if g.len() > 1 {
+            f = p_divrem(&f, &g, p).0;
+            h = p_divrem(&h, &f, p).1;
+            res.push((g, d));
+        }
+        d += 1;
+    }
+    if f.len() > 1 {
+        let d = f.len() - 1;
+        res.push((f, d));
+    }
+    res
+}
+
+// 次数 d の既約因子だけからなる g を Cantor-Zassenhaus で分ける
+fn equal_degree(g: UPoly, d: usize, p: u64, rng: &mut XorShift) -> Vec<UPoly> {
+    if g.len() - 1 == d {
+        return vec![g];
+    }
+    loop {
+        let mut a: UPoly = (0..g.len() - 1).map(|_| rng.next() % p).collect();
+        trim(&mut a);
+        if a.len() < 2 {
+            continue;
+        }
+        // a^((p^d - 1)/2) = (a a^p ... a^(p^(d-1)))^((p-1)/2)
+        let mut b = a.clone();
+        let mut fr = a;
+        for _ in 1..d {
+            fr = p_powmod(&fr, p, &g, p);
+            b = p_divrem(&p_mul(&b, &fr, p), &g, p).1;
+        }
+        let b = p_powmod(&b, (p - 1) / 2, &g, p);
+        let c = p_gcd(&p_sub(&b, &vec![1], p), &g, p);
+        if 1 < c.len() && c.len() < g.len() {
+            let h = p_divrem(&g, &c, p).0;
+            let mut res = equal_degree(c, d, p, rng);
+            res.extend(equal_degree(h, d, p, rng));
+            return res;
+        }
+    }
+}
+
+fn factor_mod_p(f: &UPoly, p: u64) -> Vec<UPoly> {
+    let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+    let mut res = vec![];
+    for (g, d) in distinct_degree(p_monic(f, p), p) {
+        res.extend(equal_degree(g, d, p, &mut rng));
+    }
+    res
+}
+
+fn is_prime(n: u64) -> bool {
+    n >= 2 && (2..).take_while(|i| i * i <= n).all(|i| n % i != 0)
+}
+
+/*
+Polynomials over Z
+*/
+
+type ZPoly = Vec<BigInt>;
+
+fn z_trim(f: &mut ZPoly) {
+    while f.last().is_some_and(|c| c.is_zero()) {
+        f.pop();
+    }
+}
+
+fn z_mul(f: &ZPoly, g: &ZPoly) -> ZPoly {
+    if f.is_empty() || g.is_empty() {
+        return vec![];
+    }
+    let mut res = vec![BigInt::zero(); f.len() + g.len() - 1];
+    for (i, a) in f.iter().enumerate() {
+        for (j, b) in g.iter().enumerate() {
+            res[i + j] += a * b;
+        }
+    }
+    z_trim(&mut res);
+    res
+}
+
+// 割り切れるときだけ商を返す
+fn z_div_exact(f: &ZPoly, g: &ZPoly) -> Option<ZPoly> {
+    let mut r = f.clone();
+    if r.len() < g.len() {
+        return if r.is_empty() { Some(vec![]) } else { None };
+    }
+    let lg = g.last().unwrap();
+    let mut q = vec![BigInt::zero(); r.len() - g.len() + 1];
+    while r.len() >= g.len() {
+        let k = r.len() - g.len();
+        let (c, rem) = r.last().unwrap().div_rem(lg);
+        if !rem.is_zero() {
+            return None;
+        }
+        for (j, b) in g.iter().enumerate() {
+            r[k + j] -= &c * b;
+        }
+        q[k] = c;
+        z_trim(&mut r);
+    }
+    if r.is_empty() {
+        z_trim(&mut q);
+        Some(q)
+    } else {
+        None
+    }
+}
+
+// 係数の gcd で割って先頭を正にする
+fn z_primitive(f: &ZPoly) -> ZPoly {
+    let mut g = BigInt::zero();
+    for c in f {
+        g = g.gcd(c);
+    }
+    if f.last().is_some_and(|c| c.is_negative()) {
+        g = -g;
+    }
+    f.iter().map(|c| c / &g).collect()
+}
+
+fn z_diff(f: &ZPoly) -> ZPoly {
+    let mut res: ZPoly = f
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, c)| c * BigInt::from(i))
+        .collect();
+    z_trim(&mut res);
+    res
+}
+
+// 擬剰余. 係数は多倍長なので Poly::prem と違って溢れない
+fn z_prem(a: &ZPoly, b: &ZPoly) -> ZPoly {
+    let lb = b.last().unwrap();
+    let mut r = a.clone();
+    while r.len() >= b.len() {
+        let k = r.len() - b.len();
+        let lr = r.last().unwrap().clone();
+        for c in r.iter_mut() {
+            *c *= lb;
+        }
+        for (j, c) in b.iter().enumerate() {
+            r[k + j] -= &lr * c;
+        }
+        z_trim(&mut r);
+    }
+    r
+}
+
+// 原始的 PRS. 結果は z_primitive で正規化
+fn z_gcd(a: &ZPoly, b: &ZPoly) -> ZPoly {
+    let (mut r0, mut r1) = (z_primitive(a), z_primitive(b));
+    if r0.len() < r1.len() {
+        std::mem::swap(&mut r0, &mut r1);
+    }
+    while !r1.is_empty() {
+        let r = z_prem(&r0, &r1);
+        r0 = r1;
+        r1 = if r.is_empty() { r } else { z_primitive(&r) };
+    }
+    r0
+}
+
+// 原始的な f に Yun のアルゴリズム. (因子, 重複度) の列
+fn z_square_free(f: &ZPoly) -> Vec<(ZPoly, usize)> {
+    let df = z_diff(f);
+    let a = z_gcd(f, &df);
+    let mut b = z_div_exact(f, &a).expect("gcd divides");
+    let c = z_div_exact(&df, &a).expect("gcd divides");
+    let mut d = z_sub(&c, &z_diff(&b));
+    let mut res = vec![];
+    let mut i = 1;
+    while b.len() > 1 {
+        let a = z_gcd(&b, &d);
+        let nb = z_div_exact(&b, &a).expect("gcd divides");
+        d = z_sub(&z_div_exact(&d, &a).expect("gcd divides"), &z_diff(&nb));
+        b = nb;
+        if a.len() > 1 {
+            res.push((a, i));
+        }
+        i += 1;
+    }
+    res
+}
+
+fn z_sub(f: &ZPoly, g: &ZPoly) -> ZPoly {
+    let mut res = f.clone();
+    res.resize(f.len().max(g.len()), BigInt::zero());
+    for (i, c) in g.iter().enumerate() {
+        res[i] -= c;
+    }
+    z_trim(&mut res);
+    res
+}
+
+fn z_to_p(f: &ZPoly, p: u64) -> UPoly {
+    let pb = BigInt::from(p);
+    let mut res: UPoly = f
+        .iter()
+        .map(|c| c.mod_floor(&pb).to_u64().unwrap())
+        .collect();
+    trim(&mut res);
+    res
+}
+
+fn p_to_z(f: &UPoly) -> ZPoly {
+    f.iter().map(|c| BigInt::from(*c)).collect()
+}
+
+// f ≡ g h (mod p) を mod p^k まで持ち上げる. g は monic, 係数は [0, p^k)
+fn hensel_lift(f: &ZPoly, g: &UPoly, h: &UPoly, p: u64, k: u32) -> (ZPoly, ZPoly) {
+    let (_, s, t) = p_ext_gcd(g, h, p);
+    let (mut gz, mut hz) = (p_to_z(g), p_to_z(h));
+    let pb = BigInt::from(p);
+    let mut m = pb.clone();
+    for _ in 1..k {
+        let gh = z_mul(&gz, &hz);
+        let mut e = f.clone();
+        e.resize(e.len().max(gh.len()), BigInt::zero());
+        for (i, c) in gh.iter().enumerate() {
+            e[i] -= c;
+        }
+        let e: ZPoly = e.iter().map(|c| c / &m).collect();
+        let e = z_to_p(&e, p);
+        let (q, dg) = p_divrem(&p_mul(&t, &e, p), g, p);
+        let dh = p_add(&p_mul(&s, &e, p), &p_mul(&q, h, p), p);
+        for (zs, d) in [(&mut gz, dg), (&mut hz, dh)] {
+            zs.resize(zs.len().max(d.len()), BigInt::zero());
+            for (i, c) in d.iter().enumerate() {
+                zs[i] += &m * BigInt::from(*c);
+            }
+        }
+        m *= &pb;
+    }
+    (gz, hz)
+}
+
+// 整数係数, 原始的, 無平方な一変数多項式を既約因子に分ける
+fn factor_z(f: &ZPoly) -> Vec<ZPoly> {
+    let n = f.len() - 1;
+    if n <= 1 {
+        return vec![f.clone()];
+    }
+    let lc = f.last().unwrap().clone();
+    // mod p でも無平方で次数が落ちない素数のうち, 因子の少ないものを使う
+    let mut best: Option<(u64, Vec<UPoly>)> = None;
+    let mut tried = 0;
+    for p in (3..).filter(|p| is_prime(*p)) {
+        let fp = z_to_p(f, p);
+        if fp.len() != f.len() || p_gcd(&fp, &p_diff(&fp, p), p).len() != 1 {
+            continue;
+        }
+        let fs = factor_mod_p(&fp, p);
+        if best.as_ref().map_or(true, |(_, b)| fs.len() < b.len()) {
+            best = Some((p, fs));
+        }
+        tried += 1;
+        if tried == 3 {
+            break;
+        }
+    }
+    let (p, mut us) = best.expect("good prime");
+    if us.len() == 1 {
+        return vec![f.clone()];
+    }
+    // Mignotte の評価 |lc| 2^n ||f||_1 の 2 倍より大きな p^k まで持ち上げる
+    let norm: BigInt = f.iter().map(|c| c.abs()).sum();
+    let bound = lc.abs() * BigInt::from(2).pow(n as u32 + 1) * norm;
+    let (mut k, mut m) = (1, BigInt::from(p));
+    while m <= bound {
+        m *= p;
+        k += 1;
+    }
+    // 一つずつ切り離しながら持ち上げる
+    let lcp = z_to_p(&vec![lc.clone()], p)[0];
+    let mut lifted: Vec<ZPoly> = vec![];
+    let mut cur = f.clone();
+    let last = us.pop().unwrap();
+    for (i, u) in us.iter().enumerate() {
+        let rest = us[i + 1..]
+            .iter()
+            .fold(p_scale(&last, lcp, p), |acc, v| p_mul(&acc, v, p));
+        let (g, h) = hensel_lift(&cur, u, &rest, p, k);
+        lifted.push(g);
+        cur = h;
+    }
+    // 最後は先頭係数が lc なので monic にする
+    let li = lc.mod_floor(&m).extended_gcd(&m).x.mod_floor(&m);
+    lifted.push(cur.iter().map(|c| (c * &li).mod_floor(&m)).collect());
+
+    // 組み合わせを小さい順に試す
+    let mut res = vec![];
+    let mut f = f.clone();
+    let mut s = 1;
+    while 2 * s <= lifted.len() {
+        let lc = f.last().unwrap().clone();
+        let mut found = None;
+        for sub in (0..lifted.len()).combinations(s) {
+            let mut g = vec![lc.clone()];
+            for i in &sub {
+                g = z_mul(&g, &lifted[*i])
+                    .iter()
+                    .map(|c| c.mod_floor(&m))
+                    .collect();
+            }
+            // 対称な代表元に
+            let half = &m / 2;
+            let g: ZPoly = g
+                .into_iter()
+                .map(|c| if c > half { c - &m } else { c })
+                .collect();
+            let g = z_primitive(&g);
+            if let Some(q) = z_div_exact(&f, &g) {
+                found = Some((sub, g, q));
+                break;
+            }
+        }
+        match found {
+            Some((sub, g, q)) => {
+                res.push(g);
+                f = q;
+                for i in sub.into_iter().rev() {
+                    lifted.remove(i);
+                }
+            }
+            None => s += 1,
+        }
+    }
+    res.push(z_primitive(&f));
+    res
+}
+
+/*
+Kronecker Substitution
+*/
+// x_i を t^(B_0 ... B_(i-1)) に送る. B_i は x_i の次数 + 1 なので, f の因子は一意に戻せる
+
+struct Kronecker {
+    vars: Vec<Var>,
+    radix: Vec<usize>,
+}
+
+impl Kronecker {
+    fn new(f: &Poly) -> Kronecker {
+        let vars = f.get_vars();
+        let radix = vars.iter().map(|v| f.deg_in(*v) + 1).collect();
+        Kronecker { vars, radix }
+    }
+    fn to_uni(&self, f: &Poly) -> ZPoly {
+        let mut res = vec![];
+        for Reverse(m) in &f.mons {
+            let mut e = 0;
+            let mut w = 1;
+            for (v, b) in self.vars.iter().zip(&self.radix) {
+                e += m.vars.get(v).unwrap_or(&0) * w;
+                w *= b;
+            }
+            if res.len() <= e {
+                res.resize(e + 1, BigInt::zero());
+            }
+            res[e] = BigInt::from(*m.coef.numer());
+        }
+        res
+    }
+    // 係数が i64 に収まらなければ f の因子ではない
+    fn lift_uni(&self, u: &ZPoly, r: &RingRef) -> Option<Poly> {
+        let mut mons = vec![];
+        for (e, c) in u.iter().enumerate() {
+            if c.is_zero() {
+                continue;
+            }
+            let mut e = e;
+            let mut vars = HashMap::new();
+            for (v, b) in self.vars.iter().zip(&self.radix) {
+                if e % b != 0 {
+                    vars.insert(*v, e % b);
+                }
+                e /= b;
+            }
+            let mut m: Mon<C> = Mon::from((vars, r));
+            m.coef = C::from_integer(c.to_i64()?);
+            mons.push(m);
+        }
+        Some(Poly::from((mons, r)))
+    }
+}
+
+// 原始的で無平方な f を既約因子に分ける
+fn factor_sqf(f: &Poly) -> Result<Vec<Poly>, CoefOverflow> {
+    let vs = f.get_vars();
+    if vs.is_empty() {
+        return Ok(vec![]);
+    }
+    // 変数を含まない因子は内容として先に取り出す
+    for v in &vs {
+        let c = f.content_in(*v)?;
+        if !c.get_vars().is_empty() {
+            let mut res = factor_sqf(&c)?;
+            res.extend(factor_sqf(&f.div_exact(&c).expect("content divides"))?);
+            return Ok(res);
+        }
+    }
+    // ある変数について一次で原始的なら既約
+    if vs.iter().any(|v| f.deg_in(*v) == 1) {
+        return Ok(vec![f.clone()]);
+    }
+    // 像は無平方とは限らない (x^2 - y^2 z^2 -> t^24 - t^2) ので, 先に無平方分解して
+    // 重複度の分だけ因子を並べる
+    let k = Kronecker::new(f);
+    let mut us = vec![];
+    for (g, e) in z_square_free(&k.to_uni(f)) {
+        for h in factor_z(&g) {
+            us.extend(std::iter::repeat(h).take(e));
+        }
+    }
+    if us.len() == 1 {
+        return Ok(vec![f.clone()]);
+    }
+    let mut res = vec![];
+    let mut f = f.clone();
+    let mut s = 1;
+    while 2 * s <= us.len() {
+        let mut found = None;
+        for sub in (0..us.len()).combinations(s) {
+            let g = sub
+                .iter()
+                .fold(vec![BigInt::from(1)], |acc, i| z_mul(&acc, &us[*i]));
+            let g = match k.lift_uni(&g, &f.r) {
+                Some(g) if !g.get_vars().is_empty() => g.try_primitive_part()?,
+                _ => continue,
+            };
+            if let Some(q) = f.div_exact(&g) {
+                found = Some((sub, g, q));
+                break;
+            }
+        }
+        match found {
+            Some((sub, g, q)) => {
+                res.push(g);
+                f = q;
+                for i in sub.into_iter().rev() {
+                    us.remove(i);
+                }
+            }
+            None => s += 1,
+        }
+    }
+    res.push(f.try_primitive_part()?);
+    Ok(res)
+}
+
+impl Poly {
+    // 定数 c と既約因子 p_i, 重複度 e_i で self = c * Π p_i^e_i
+    // p_i は primitive_part で正規化して, 重複度, 次数の順に並べる
+    pub fn factor(&self) -> Result<(C, Vec<(Poly, usize)>), CoefOverflow> {
+        let (c, sqf) = self.square_free()?;
+        let mut res = vec![];
+        for (p, e) in sqf {
+            for q in factor_sqf(&p)? {
+                res.push((q, e));
+            }
+        }
+        res.sort_by_key(|(q, e)| (*e, q.tdeg(), format!("{:?}", q)));
+        Ok((c, res))
+    }
+    pub fn is_irreducible(&self) -> Result<bool, CoefOverflow> {
+        let (_, fs) = self.factor()?;
+        Ok(fs.len() == 1 && fs[0].1 == 1)
+    }
+}
+
+impl Temp {
+    // パラメーターが全部消えていれば多項式として取り出せる
+    pub fn to_poly(&self) -> Option<Poly> {
+        let mut mons = vec![];
+        for Reverse(m) in &self.mons {
+            if !m.coef.is_cnst() {
+                return None;
+            }
+            let mut n: Mon<C> = Mon::from((m.vars.clone(), &self.r));
+            n.coef = m.coef.terms[0].coef;
+            mons.push(n);
+        }
+        Some(Poly::from((mons, &self.r)))
+    }
+    // 残ったパラメーターを一つずつ 1 に, 他を 0 にして得る多項式. 解の空間の基底になる
+    pub fn components(&self) -> Vec<Poly> {
+        let pars = self.get_pars();
+        let mut res = vec![];
+        for p in &pars {
+            let asg: Vec<(Par, LinExp)> = pars
+                .iter()
+                .map(|q| {
+                    (
+                        *q,
+                        if p == q {
+                            LinExp::one()
+                        } else {
+                            LinExp::zero()
+                        },
+                    )
+                })
+                .collect();
+            if let Some(f) = self.subs_pars(asg).to_poly() {
+                if !f.is_zero() {
+                    res.push(f.primitive_part());
+                }
+            }
+        }
+        res
+    }
+}
+
+#[test]
+fn check_factor_univariate() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let px = Poly::from((x, &r));
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    // x^4 - 1 = (x - 1)(x + 1)(x^2 + 1)
+    let p = px.pow(4) - c(1);
+    let (k, fs) = p.factor().unwrap();
+    println!("{:?} = {:?} {:?}", p, k, fs);
+    assert!(k == C::one());
+    assert!(fs.len() == 3);
+    assert!(fs.contains(&(px.clone() - c(1), 1)));
+    assert!(fs.contains(&(px.clone() + c(1), 1)));
+    assert!(fs.contains(&(px.pow(2) + c(1), 1)));
+    // x^4 + 1 は mod p ではいつも分解するが, 有理数上では既約
+    assert!((px.pow(4) + c(1)).is_irreducible().unwrap());
+    // 1/2 (2x + 3)^2 (x^2 - 2)
+    let p = Poly::from((C::new(1, 2), &r)) * (c(2) * px.clone() + c(3)).pow(2) * (px.pow(2) - c(2));
+    let (k, fs) = p.factor().unwrap();
+    println!("{:?} = {:?} {:?}", p, k, fs);
+    assert!(k == C::new(1, 2));
+    assert!(fs == vec![(px.pow(2) - c(2), 1), (c(2) * px.clone() + c(3), 2)]);
+}
+
+#[test]
+fn check_factor_multivariate() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let z = r.borrow_mut().vextend("z".to_string());
+    let (px, py, pz) = (
+        Poly::from((x, &r)),
+        Poly::from((y, &r)),
+        Poly::from((z, &r)),
+    );
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    // (y - 1)(x - y)
+    let p = (py.clone() - c(1)) * (px.clone() - py.clone());
+    let (k, fs) = p.factor().unwrap();
+    println!("{:?} = {:?} {:?}", p, k, fs);
+    assert!(fs.len() == 2);
+    let back = fs
+        .iter()
+        .fold(Poly::from((k, &r)), |acc, (f, e)| acc * f.pow(*e));
+    assert!(back == p);
+    // x^2 - y^2 z^2 = (x - yz)(x + yz), x^2 + y^2 は既約
+    let p = (px.pow(2) - py.pow(2) * pz.pow(2)) * (px.pow(2) + py.pow(2)).pow(2);
+    let (k, fs) = p.factor().unwrap();
+    println!("{:?} = {:?} {:?}", p, k, fs);
+    assert!(fs.len() == 3);
+    // 先頭項 (grevlex) の係数が正になるように正規化される
+    assert!(k == -C::one());
+    assert!(fs.contains(&(py.clone() * pz.clone() - px.clone(), 1)));
+    assert!(fs.contains(&(px.clone() + py.clone() * pz.clone(), 1)));
+    assert!(fs.contains(&(px.pow(2) + py.pow(2), 2)));
+    // 二次で一次因子に分かれない
+    assert!((px.pow(2) + py.pow(2) - pz.pow(2) + c(1))
+        .is_irreducible()
+        .unwrap());
+    assert!(!(px.pow(2) - py.pow(2)).is_irreducible().unwrap());
+    // 三つの既約因子
+    let f1 = px.pow(2) + py.clone() * pz.clone() + c(1);
+    let f2 = px.clone() * py.clone() - pz.pow(2) + c(2);
+    let f3 = px.clone() + py.clone() + pz.clone();
+    let p = f1.clone() * f2.clone() * f3.clone();
+    let (k, fs) = p.factor().unwrap();
+    println!("{:?} = {:?} {:?}", p, k, fs);
+    assert!(fs.len() == 3);
+    for f in [f1, f2, f3] {
+        assert!(fs.contains(&(f.primitive_part(), 1)));
+    }
+}
+
+#[test]
+fn check_temp_to_poly() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let px = Poly::from((x, &r));
+    let t = Temp::most_gen(1, &r);
+    assert!(t.to_poly().is_none());
+    // a1 = 1, a0 = -1 を入れると x - 1
+    let pars = t.get_pars();
+    let sol = vec![(pars[0], -LinExp::one()), (pars[1], LinExp::one())];
+    let p = t.subs_pars(sol).to_poly().expect("no parameters left");
+    assert!(p == px - Poly::one(&r));
+    assert!(p.is_irreducible().unwrap());
+}
@@ -0,0 +1,174 @@
+use super::coef::*;
+use super::expr::*;
+#[allow(unused_imports)]
+use super::expr_parse::*;
+use super::groebner::*;
+use super::ideal::*;
+use super::poly::*;
+use super::recurrence::*;
+use super::ring::*;
+use std::collections::HashMap;
+
+/*
+Forward Abstract Interpretation
+*/
+// 到達する状態で消える多項式のイデアルを前向きに伝播する (Rodríguez-Carbonell, Kapur).
+// 合流は共通部分で, ループは次数 d 以下に切り詰めながら不動点まで回す.
+// 本体が解ける漸化式なら, 閉じた形から不変式を全部求めて次数は使わない
+
+// 次数 d 以下の単項式の数 C(n + d, d)
+fn mons_upto(n: usize, d: usize) -> usize {
+    (1..=d).fold(1, |acc, i| acc * (n + i) / i)
+}
+
+// 代入の後で成り立つもの. x = a x + p (a は 0 でない定数) は逆に解いて代入する
+fn post_assign(ideal: &Ideal, asg: &[(Var, Poly)]) -> Result<Ideal, CoefOverflow> {
+    if let [(x, f)] = asg {
+        let cs = f.coeffs_in(*x);
+        if cs.len() == 2 && cs[1].get_vars().is_empty() {
+            let a = cs[1].eval(&HashMap::new());
+            let inv =
+                (Poly::from((*x, &ideal.r)) - cs[0].clone()) * Poly::from((C::one() / a, &ideal.r));
+            let map: HashMap<Var, Poly> = vec![(*x, inv)].into_iter().collect();
+            let gens = ideal.gens.iter().map(|g| g.subs_many(&map)).collect();
+            return Ok(Ideal::new(gens, &ideal.r));
+        }
+    }
+    Ok(Ideal::new(
+        eliminate_old(&ideal.gens, asg, &ideal.r)?,
+        &ideal.r,
+    ))
+}
+
+// ガードを仮定する. p = 0 なら足して, p ≠ 0 なら p で飽和させる
+fn assume(ideal: &Ideal, guard: &Pred, holds: bool) -> Result<Ideal, CoefOverflow> {
+    let p = Ideal::new(vec![guard.p.clone()], &ideal.r);
+    if guard.eq == holds {
+        Ok(ideal.sum(&p))
+    } else {
+        ideal.saturation(&p)
+    }
+}
+
+// Grevlex の基底のうち次数 d 以下のもの. 次数 d 以下の部分空間を生成する
+fn truncate(ideal: &Ideal, d: usize) -> Result<Ideal, CoefOverflow> {
+    let gens = ideal.gb()?.into_iter().filter(|g| g.tdeg() <= d).collect();
+    Ok(Ideal::new(gens, &ideal.r))
+}
+
+// e を実行した後のイデアル. invs にはループ頭の不変式を, 現れる順に積む
+pub fn forward(
+    e: &Expr,
+    ideal: Ideal,
+    d: usize,
+    invs: &mut Vec<Ideal>,
+) -> Result<Ideal, CoefOverflow> {
+    match e {
+        Expr::Ass { lv, rv } => post_assign(&ideal, &[(*lv, rv.clone())]),
+        Expr::ParAss { lvs, rvs } => {
+            let asg: Vec<(Var, Poly)> = lvs.iter().copied().zip(rvs.iter().cloned()).collect();
+            post_assign(&ideal, &asg)
+        }
+        Expr::Skip => Ok(ideal),
+        Expr::Seq { exprs } => exprs.iter().try_fold(ideal, |i, e| forward(e, i, d, invs)),
+        Expr::If { guard, the, els } => {
+            let i1 = forward(the, assume(&ideal, guard, true)?, d, invs)?;
+            let i2 = forward(els, assume(&ideal, guard, false)?, d, invs)?;
+            i1.intersection(&i2)
+        }
+        Expr::While { guard, c } => {
+            // 内側のループの不変式は, 不動点での一回分だけ残す
+            if let Some(head) = loop_invariant(c, &ideal)? {
+                invs.push(head.clone());
+                return assume(&head, guard, false);
+            }
+            // head は次数 d 以下の元で生成されていて, 反復ごとに小さくなる.
+            // 不動点でなければ次数 d 以下の部分 (次元 C(n + d, d) 以下) が真に減るので,
+            // その回数以内に必ず止まる
+            let n = ideal.r.borrow().var_list().len();
+            let mut head = truncate(&ideal, d)?;
+            let mut fixed = false;
+            for _ in 0..=mons_upto(n, d) {
+                let body = forward(c, assume(&head, guard, true)?, d, &mut vec![])?;
+                let next = truncate(&head.intersection(&body)?, d)?;
+                if next.same(&head)? {
+                    fixed = true;
+                    break;
+                }
+                head = next;
+            }
+            assert!(fixed, "loop head did not reach a fixpoint");
+            invs.push(head.clone());
+            forward(c, assume(&head, guard, true)?, d, invs)?;
+            assume(&head, guard, false)
+        }
+    }
+}
+
+#[test]
+fn forward_counter() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let e = program()
+        .parse(
+            r#"
+            x = 0; y = 0; s = 0;
+            while (x - n != 0) {
+                s = s + 2 * x + 1;
+                x = x + 1;
+                y = y + 2;
+            }
+            "#,
+        )
+        .map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let v = |s: &str| Poly::from((r.borrow().var_by_name(s).unwrap(), &r));
+    let mut invs = vec![];
+    let post = forward(&e, Ideal::zero(&r), 2, &mut invs).unwrap();
+    println!("invariants: {:?}", invs);
+    println!("post: {:?}", post);
+    assert!(invs.len() == 1);
+    // y = 2x, s = x^2
+    assert!(invs[0]
+        .contains(&(v("y") - Poly::from((C::new(2, 1), &r)) * v("x")))
+        .unwrap());
+    assert!(invs[0].contains(&(v("s") - v("x").pow(2))).unwrap());
+    // 出口では x = n
+    assert!(post.contains(&(v("s") - v("n").pow(2))).unwrap());
+    // 本体が解けるので, 次数 1 でも s = x^2 が見つかる
+    let post = forward(&e, Ideal::zero(&r), 1, &mut vec![]).unwrap();
+    assert!(post.contains(&(v("s") - v("n").pow(2))).unwrap());
+    assert!(post
+        .contains(&(v("y") - Poly::from((C::new(2, 1), &r)) * v("n")))
+        .unwrap());
+}
+
+#[test]
+fn forward_mannadiv() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let e = program()
+        .parse(
+            r#"
+            y1 = 0; y2 = 0; y3 = x1;
+            while (y3 != 0) {
+                if (y2 + 1 == x2) {
+                    y1 = y1 + 1;
+                    y2 = 0;
+                    y3 = y3 - 1;
+                } else {
+                    y2 = y2 + 1;
+                    y3 = y3 - 1;
+                }
+            }"#,
+        )
+        .map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let v = |s: &str| Poly::from((r.borrow().var_by_name(s).unwrap(), &r));
+    let mut invs = vec![];
+    forward(&e, Ideal::zero(&r), 2, &mut invs).unwrap();
+    println!("invariants: {:?}", invs);
+    // 後ろ向きの方法で見つかるものと同じ y1 x2 + y2 + y3 - x1 = 0
+    let inv = v("y1") * v("x2") + v("y2") + v("y3") - v("x1");
+    assert!(invs[0].contains(&inv).unwrap());
+}
@@ -0,0 +1,802 @@
+use super::coef::*;
+use super::mon::*;
+use super::poly::*;
+use super::ring::*;
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/*
+Rational Content
+*/
+
+fn big(c: C) -> BigRational {
+    BigRational::new(BigInt::from(*c.numer()), BigInt::from(*c.denom()))
+}
+
+fn small(c: &BigRational) -> Result<C, CoefOverflow> {
+    match (c.numer().to_i64(), c.denom().to_i64()) {
+        (Some(n), Some(d)) => Ok(C::new(n, d)),
+        _ => Err(CoefOverflow),
+    }
+}
+
+impl Poly {
+    // 分母の lcm は i64 に収まらないことがあるので多倍長で求める
+    fn big_content(&self) -> BigRational {
+        let (mut g, mut l) = (BigInt::zero(), BigInt::one());
+        for Reverse(m) in &self.mons {
+            g = g.gcd(&BigInt::from(*m.coef.numer()));
+            l = l.lcm(&BigInt::from(*m.coef.denom()));
+        }
+        let c = BigRational::new(g, l);
+        if self.mons[0].0.coef < C::zero() {
+            -c
+        } else {
+            c
+        }
+    }
+    // 係数の分子の gcd / 分母の lcm. 先頭係数の符号をつける
+    // self / content は, 先頭が正で互いに素な整数係数になる
+    pub fn content(&self) -> Result<C, CoefOverflow> {
+        if self.is_zero() {
+            return Ok(C::zero());
+        }
+        small(&self.big_content())
+    }
+    pub fn try_primitive_part(&self) -> Result<Poly, CoefOverflow> {
+        if self.is_zero() {
+            return Ok(self.clone());
+        }
+        let c = self.big_content().recip();
+        let mut p = self.clone();
+        for Reverse(m) in &mut p.mons {
+            m.coef = small(&(big(m.coef) * &c))?;
+        }
+        Ok(p)
+    }
+    // 表示用. 整数係数が i64 に収まらなければ panic する
+    pub fn primitive_part(&self) -> Poly {
+        self.try_primitive_part()
+            .expect("primitive part fits in i64")
+    }
+}
+
+/*
+Polynomials in a Main Variable
+*/
+// v についての一変数多項式とみて, 係数は v を含まない Poly
+
+impl Poly {
+    // 現れる変数. 番号順
+    pub fn get_vars(&self) -> Vec<Var> {
+        let mut vs: Vec<Var> = self
+            .mons
+            .iter()
+            .flat_map(|m| m.0.vars.keys().copied())
+            .collect();
+        vs.sort();
+        vs.dedup();
+        vs
+    }
+    pub fn deg_in(&self, v: Var) -> usize {
+        self.mons
+            .iter()
+            .map(|m| *m.0.vars.get(&v).unwrap_or(&0))
+            .max()
+            .unwrap_or(0)
+    }
+    // i 番目が v^i の係数
+    pub fn coeffs_in(&self, v: Var) -> Vec<Poly> {
+        let mut cs = vec![vec![]; self.deg_in(v) + 1];
+        for Reverse(m) in &self.mons {
+            let mut m = m.clone();
+            let d = m.vars.remove(&v).unwrap_or(0);
+            cs[d].push(m);
+        }
+        cs.into_iter().map(|ms| Poly::from((ms, &self.r))).collect()
+    }
+    // v についての先頭係数
+    pub fn lc_in(&self, v: Var) -> Poly {
+        self.coeffs_in(v).pop().expect("coefficients")
+    }
+    // 係数の gcd. v を含まない
+    pub fn content_in(&self, v: Var) -> Result<Poly, CoefOverflow> {
+        let mut g = Poly::zero(&self.r);
+        for c in self.coeffs_in(v) {
+            g = g.gcd(&c)?;
+            if g.get_vars().is_empty() && !g.is_zero() {
+                break;
+            }
+        }
+        Ok(g)
+    }
+    pub fn primitive_part_in(&self, v: Var) -> Result<Poly, CoefOverflow> {
+        if self.is_zero() {
+            return Ok(self.clone());
+        }
+        self.div_exact(&self.content_in(v)?)
+            .expect("content divides")
+            .try_primitive_part()
+    }
+
+    // 割り切れるときだけ商を返す. 割り切れるなら, 先頭項はいつも先頭項で割れる
+    pub fn div_exact(&self, other: &Poly) -> Option<Poly> {
+        self.r.assert_same(&other.r);
+        assert!(!other.is_zero(), "division by zero polynomial");
+        let lt = &other.mons[0].0;
+        let mut f = self.clone();
+        let mut q = vec![];
+        while !f.is_zero() {
+            let lf = &f.mons[0].0;
+            let mut vars = lf.vars.clone();
+            for (v, d) in &lt.vars {
+                match vars.get_mut(v) {
+                    Some(e) if *e > *d => *e -= d,
+                    Some(e) if *e == *d => drop(vars.remove(v)),
+                    _ => return None,
+                }
+            }
+            let mut t: Mon<C> = Mon::from((vars, &self.r));
+            t.coef = lf.coef / lt.coef;
+            f -= Poly::from((vec![t.clone()], &self.r)) * other.clone();
+            q.push(t);
+        }
+        Some(Poly::from((q, &self.r)))
+    }
+
+    // v についての擬剰余. lc(b)^k * a = q * b + r となる r
+    pub fn prem(&self, b: &Poly, v: Var) -> Poly {
+        let db = b.deg_in(v);
+        let lb = b.lc_in(v);
+        let mut r = self.clone();
+        while !r.is_zero() && r.deg_in(v) >= db {
+            let dr = r.deg_in(v);
+            // v^0 を変数として残さないように
+            let shift = match dr - db {
+                0 => Poly::one(&self.r),
+                k => Poly::from((vec![Mon::from((vec![(v, k)], &self.r))], &self.r)),
+            };
+            r = lb.clone() * r.clone() - r.lc_in(v) * shift * b.clone();
+        }
+        r
+    }
+}
+
+/*
+Recursive Polynomials over Z
+*/
+// gcd の途中は係数も次数も膨らむので, 多倍長整数の再帰的な表現で計算する.
+// 変数を一つ決めるごとに一段下がり, 一番下は整数. 係数は低い次数から並べる
+
+#[derive(Clone, PartialEq, Debug)]
+enum RPoly {
+    Int(BigInt),
+    Up(Vec<RPoly>),
+}
+
+impl RPoly {
+    fn is_zero(&self) -> bool {
+        match self {
+            RPoly::Int(n) => n.is_zero(),
+            RPoly::Up(cs) => cs.is_empty(),
+        }
+    }
+    // 同じ段の 0
+    fn zero_like(&self) -> RPoly {
+        match self {
+            RPoly::Int(_) => RPoly::Int(BigInt::zero()),
+            RPoly::Up(_) => RPoly::Up(vec![]),
+        }
+    }
+    fn up(mut cs: Vec<RPoly>) -> RPoly {
+        while cs.last().is_some_and(|c| c.is_zero()) {
+            cs.pop();
+        }
+        RPoly::Up(cs)
+    }
+    fn coeffs(&self) -> &Vec<RPoly> {
+        match self {
+            RPoly::Up(cs) => cs,
+            RPoly::Int(_) => panic!("integer has no coefficients"),
+        }
+    }
+    fn deg(&self) -> usize {
+        self.coeffs().len().saturating_sub(1)
+    }
+    // 再帰的にたどった先頭の整数係数
+    fn lead_int(&self) -> &BigInt {
+        match self {
+            RPoly::Int(n) => n,
+            RPoly::Up(cs) => cs.last().expect("zero polynomial").lead_int(),
+        }
+    }
+    fn add(&self, rhs: &RPoly) -> RPoly {
+        match (self, rhs) {
+            (RPoly::Int(a), RPoly::Int(b)) => RPoly::Int(a + b),
+            (RPoly::Up(a), RPoly::Up(b)) => {
+                let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+                let mut cs = long.clone();
+                for (i, c) in short.iter().enumerate() {
+                    cs[i] = cs[i].add(c);
+                }
+                RPoly::up(cs)
+            }
+            _ => panic!("level mismatch"),
+        }
+    }
+    fn neg(&self) -> RPoly {
+        match self {
+            RPoly::Int(a) => RPoly::Int(-a),
+            RPoly::Up(cs) => RPoly::Up(cs.iter().map(|c| c.neg()).collect()),
+        }
+    }
+    fn sub(&self, rhs: &RPoly) -> RPoly {
+        self.add(&rhs.neg())
+    }
+    fn mul(&self, rhs: &RPoly) -> RPoly {
+        match (self, rhs) {
+            (RPoly::Int(a), RPoly::Int(b)) => RPoly::Int(a * b),
+            (RPoly::Up(a), RPoly::Up(b)) => {
+                if a.is_empty() || b.is_empty() {
+                    return RPoly::Up(vec![]);
+                }
+                let mut cs = vec![a[0].zero_like(); a.len() + b.len() - 1];
+                for (i, x) in a.iter().enumerate() {
+                    for (j, y) in b.iter().enumerate() {
+                        cs[i + j] = cs[i + j].add(&x.mul(y));
+                    }
+                }
+                RPoly::up(cs)
+            }
+            _ => panic!("level mismatch"),
+        }
+    }
+    // 一段下の c を係数全部に掛ける
+    fn scale(&self, c: &RPoly) -> RPoly {
+        RPoly::up(self.coeffs().iter().map(|x| x.mul(c)).collect())
+    }
+    // 割り切れるときだけ商
+    fn div_exact(&self, rhs: &RPoly) -> Option<RPoly> {
+        match (self, rhs) {
+            (RPoly::Int(a), RPoly::Int(b)) => {
+                let (q, r) = a.div_rem(b);
+                if r.is_zero() {
+                    Some(RPoly::Int(q))
+                } else {
+                    None
+                }
+            }
+            (RPoly::Up(_), RPoly::Up(b)) => {
+                let mut r = self.clone();
+                if r.is_zero() {
+                    return Some(r);
+                }
+                if r.deg() < rhs.deg() {
+                    return None;
+                }
+                let lb = b.last().expect("division by zero polynomial");
+                let mut q = vec![lb.zero_like(); r.deg() - rhs.deg() + 1];
+                while !r.is_zero() && r.deg() >= rhs.deg() {
+                    let k = r.deg() - rhs.deg();
+                    let c = r.coeffs().last().unwrap().div_exact(lb)?;
+                    let mut shifted = vec![lb.zero_like(); k];
+                    shifted.extend(b.iter().map(|x| x.mul(&c)));
+                    r = r.sub(&RPoly::Up(shifted));
+                    q[k] = c;
+                }
+                if r.is_zero() {
+                    Some(RPoly::up(q))
+                } else {
+                    None
+                }
+            }
+            _ => panic!("level mismatch"),
+        }
+    }
+    // 擬剰余 lc(b)^k a mod b
+    fn prem(&self, b: &RPoly) -> RPoly {
+        let lb = b.coeffs().last().unwrap();
+        let mut r = self.clone();
+        while !r.is_zero() && r.deg() >= b.deg() {
+            let k = r.deg() - b.deg();
+            let lr = r.coeffs().last().unwrap().clone();
+            let mut shifted = vec![lb.zero_like(); k];
+            shifted.extend(b.coeffs().iter().map(|x| x.mul(&lr)));
+            r = r.scale(lb).sub(&RPoly::Up(shifted));
+        }
+        r
+    }
+    // 係数の gcd
+    fn content(&self) -> RPoly {
+        let cs = self.coeffs();
+        let mut g = cs[0].zero_like();
+        for c in cs {
+            g = g.gcd(c);
+        }
+        g
+    }
+    fn primitive(&self) -> RPoly {
+        let c = self.content();
+        RPoly::up(
+            self.coeffs()
+                .iter()
+                .map(|x| x.div_exact(&c).expect("content divides"))
+                .collect(),
+        )
+    }
+    // 先頭の整数係数が正になるように
+    fn normalize(self) -> RPoly {
+        if !self.is_zero() && self.lead_int().is_negative() {
+            self.neg()
+        } else {
+            self
+        }
+    }
+    fn gcd(&self, rhs: &RPoly) -> RPoly {
+        match (self, rhs) {
+            (RPoly::Int(a), RPoly::Int(b)) => RPoly::Int(a.gcd(b)),
+            _ if self.is_zero() => rhs.clone().normalize(),
+            _ if rhs.is_zero() => self.clone().normalize(),
+            _ => {
+                let c = self.content().gcd(&rhs.content());
+                let (mut r0, mut r1) = (self.primitive(), rhs.primitive());
+                if r0.deg() < r1.deg() {
+                    std::mem::swap(&mut r0, &mut r1);
+                }
+                // 原始的 PRS
+                while r1.deg() > 0 {
+                    let r = r0.prem(&r1);
+                    if r.is_zero() {
+                        break;
+                    }
+                    r0 = r1;
+                    r1 = r.primitive();
+                }
+                if r1.deg() == 0 {
+                    r1 = RPoly::Up(vec![c]);
+                } else {
+                    r1 = r1.scale(&c);
+                }
+                r1.normalize()
+            }
+        }
+    }
+
+    fn pow(&self, e: usize, one: &RPoly) -> RPoly {
+        (0..e).fold(one.clone(), |acc, _| acc.mul(self))
+    }
+    // 部分終結式 PRS で終結式を計算する. 主変数は一番外側で, 結果は一段下
+    fn resultant(&self, rhs: &RPoly, one: &RPoly) -> RPoly {
+        if self.is_zero() || rhs.is_zero() {
+            return one.zero_like();
+        }
+        let (mut a, mut b) = (self.clone(), rhs.clone());
+        let mut s = false;
+        if a.deg() < b.deg() {
+            std::mem::swap(&mut a, &mut b);
+            s = a.deg() % 2 == 1 && b.deg() % 2 == 1;
+        }
+        // 定数との終結式は定数の冪
+        if b.deg() == 0 {
+            let r = b.coeffs()[0].pow(a.deg(), one);
+            return if s { r.neg() } else { r };
+        }
+        let (ca, cb) = (a.content(), b.content());
+        let t = ca.pow(b.deg(), one).mul(&cb.pow(a.deg(), one));
+        a = a.primitive();
+        b = b.primitive();
+        let (mut g, mut h) = (one.clone(), one.clone());
+        while b.deg() > 0 {
+            let d = a.deg() - b.deg();
+            if a.deg() % 2 == 1 && b.deg() % 2 == 1 {
+                s = !s;
+            }
+            let r = a.prem(&b);
+            if r.is_zero() {
+                return one.zero_like();
+            }
+            a = b;
+            let q = g.mul(&h.pow(d, one));
+            b = RPoly::up(
+                r.coeffs()
+                    .iter()
+                    .map(|c| c.div_exact(&q).expect("subresultant"))
+                    .collect(),
+            );
+            g = a.coeffs().last().unwrap().clone();
+            h = match d {
+                0 => h,
+                _ => g
+                    .pow(d, one)
+                    .div_exact(&h.pow(d - 1, one))
+                    .expect("subresultant"),
+            };
+        }
+        let lb = b.coeffs()[0].pow(a.deg(), one);
+        let r = t.mul(
+            &lb.div_exact(&h.pow(a.deg() - 1, one))
+                .expect("subresultant"),
+        );
+        if s {
+            r.neg()
+        } else {
+            r
+        }
+    }
+
+    // vs[0..n] の n 段に. 係数は整数のものだけ
+    fn from_poly(p: &Poly, vs: &[Var]) -> RPoly {
+        let mut res = RPoly::zero_level(vs.len());
+        for Reverse(m) in &p.mons {
+            if m.coef.is_zero() {
+                continue;
+            }
+            assert!(m.coef.is_integer(), "integer coefficients expected");
+            let exps: Vec<usize> = vs.iter().map(|v| *m.vars.get(v).unwrap_or(&0)).collect();
+            res = res.add(&RPoly::term(&exps, BigInt::from(*m.coef.numer())));
+        }
+        res
+    }
+    fn zero_level(n: usize) -> RPoly {
+        if n == 0 {
+            RPoly::Int(BigInt::zero())
+        } else {
+            RPoly::Up(vec![])
+        }
+    }
+    // c * vs[0]^exps[0] ... , 一番外側が exps の最後
+    fn term(exps: &[usize], c: BigInt) -> RPoly {
+        match exps.split_last() {
+            None => RPoly::Int(c),
+            Some((e, rest)) => {
+                let mut cs = vec![RPoly::zero_level(rest.len()); *e];
+                cs.push(RPoly::term(rest, c));
+                RPoly::Up(cs)
+            }
+        }
+    }
+    // 係数に k を掛けて戻す. C に収まらなければ CoefOverflow
+    fn to_poly(&self, vs: &[Var], r: &RingRef, k: &BigRational) -> Result<Poly, CoefOverflow> {
+        let mut mons = vec![];
+        self.collect_mons(vs, &mut vec![], &mut mons, r, k)?;
+        Ok(Poly::from((mons, r)))
+    }
+    fn collect_mons(
+        &self,
+        vs: &[Var],
+        exps: &mut Vec<usize>,
+        mons: &mut Vec<Mon<C>>,
+        r: &RingRef,
+        k: &BigRational,
+    ) -> Result<(), CoefOverflow> {
+        match self {
+            RPoly::Int(n) => {
+                if n.is_zero() {
+                    return Ok(());
+                }
+                // exps は外側の変数から積んである
+                let vars: HashMap<Var, usize> = exps
+                    .iter()
+                    .rev()
+                    .zip(vs)
+                    .filter(|(e, _)| **e > 0)
+                    .map(|(e, v)| (*v, *e))
+                    .collect();
+                let mut m: Mon<C> = Mon::from((vars, r));
+                m.coef = small(&(k * BigRational::from_integer(n.clone())))?;
+                mons.push(m);
+            }
+            RPoly::Up(cs) => {
+                for (e, c) in cs.iter().enumerate() {
+                    exps.push(e);
+                    c.collect_mons(vs, exps, mons, r, k)?;
+                    exps.pop();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/*
+GCD
+*/
+// 整数係数に直してから RPoly で計算する. 結果は primitive_part で正規化する.
+// 途中は多倍長なので, 結果の係数が i64 に収まらないときだけ CoefOverflow になる
+
+impl Poly {
+    pub fn gcd(&self, other: &Poly) -> Result<Poly, CoefOverflow> {
+        self.r.assert_same(&other.r);
+        if self.is_zero() {
+            return other.try_primitive_part();
+        }
+        if other.is_zero() {
+            return self.try_primitive_part();
+        }
+        let mut vs = self.get_vars();
+        vs.extend(other.get_vars());
+        vs.sort();
+        vs.dedup();
+        if vs.is_empty() {
+            return Ok(Poly::one(&self.r));
+        }
+        let a = RPoly::from_poly(&self.try_primitive_part()?, &vs);
+        let b = RPoly::from_poly(&other.try_primitive_part()?, &vs);
+        a.gcd(&b)
+            .to_poly(&vs, &self.r, &BigRational::one())?
+            .try_primitive_part()
+    }
+    pub fn lcm(&self, other: &Poly) -> Result<Poly, CoefOverflow> {
+        if self.is_zero() || other.is_zero() {
+            return Ok(Poly::zero(&self.r));
+        }
+        let g = self.gcd(other)?;
+        (self.div_exact(&g).expect("gcd divides") * other.clone()).try_primitive_part()
+    }
+
+    // 定数 c と, 重複度 i ごとの無平方な因子 p_i で self = c * Π p_i^i
+    // p_i は primitive_part で正規化されていて, 1 になるものは含めない
+    pub fn square_free(&self) -> Result<(C, Vec<(Poly, usize)>), CoefOverflow> {
+        if self.is_zero() {
+            return Ok((C::zero(), vec![]));
+        }
+        let c = self.content()?;
+        let mut fs: HashMap<usize, Poly> = HashMap::new();
+        sqf_rec(&self.try_primitive_part()?, &mut fs)?;
+        let mut fs = fs
+            .into_iter()
+            .map(|(i, p)| Ok((p.try_primitive_part()?, i)))
+            .collect::<Result<Vec<(Poly, usize)>, CoefOverflow>>()?;
+        fs.sort_by_key(|(_, i)| *i);
+        Ok((c, fs))
+    }
+    // 無平方部分. 根の集合は変わらない
+    pub fn square_free_part(&self) -> Result<Poly, CoefOverflow> {
+        let (_, fs) = self.square_free()?;
+        Ok(fs
+            .into_iter()
+            .fold(Poly::one(&self.r), |acc, (p, _)| acc * p))
+    }
+}
+
+/*
+Resultant
+*/
+
+impl Poly {
+    // v についての Sylvester 行列. 成分は v を含まない
+    pub fn sylvester(&self, other: &Poly, v: Var) -> Vec<Vec<Poly>> {
+        self.r.assert_same(&other.r);
+        let (m, n) = (self.deg_in(v), other.deg_in(v));
+        let mut rows = vec![];
+        for (p, k) in [(self, n), (other, m)] {
+            let mut cs = p.coeffs_in(v);
+            cs.reverse();
+            for i in 0..k {
+                let mut row = vec![Poly::zero(&self.r); m + n];
+                for (j, c) in cs.iter().enumerate() {
+                    row[i + j] = c.clone();
+                }
+                rows.push(row);
+            }
+        }
+        rows
+    }
+    // Sylvester 行列の行列式. 小さい例での確認用
+    pub fn resultant_sylvester(&self, other: &Poly, v: Var) -> Poly {
+        det(self.sylvester(other, v), &self.r)
+    }
+    // v を消去した終結式. 部分終結式 PRS を多倍長整数で計算する
+    pub fn resultant(&self, other: &Poly, v: Var) -> Result<Poly, CoefOverflow> {
+        self.r.assert_same(&other.r);
+        if self.is_zero() || other.is_zero() {
+            return Ok(Poly::zero(&self.r));
+        }
+        let mut vs = self.get_vars();
+        vs.extend(other.get_vars());
+        vs.sort();
+        vs.dedup();
+        vs.retain(|w| *w != v);
+        let one = RPoly::term(&vec![0; vs.len()], BigInt::from(1));
+        let (a, b) = (self.try_primitive_part()?, other.try_primitive_part()?);
+        vs.push(v);
+        let res = RPoly::from_poly(&a, &vs).resultant(&RPoly::from_poly(&b, &vs), &one);
+        vs.pop();
+        // 有理数の内容は外に出す. 掛けるのも多倍長で
+        let (m, n) = (self.deg_in(v), other.deg_in(v));
+        let k = num_traits::pow(self.big_content(), n) * num_traits::pow(other.big_content(), m);
+        res.to_poly(&vs, &self.r, &k)
+    }
+}
+
+// 分数を使わない Bareiss の消去法
+fn det(mut m: Vec<Vec<Poly>>, r: &RingRef) -> Poly {
+    let n = m.len();
+    if n == 0 {
+        return Poly::one(r);
+    }
+    let mut prev = Poly::one(r);
+    let mut neg = false;
+    for k in 0..n - 1 {
+        if m[k][k].is_zero() {
+            match (k + 1..n).find(|i| !m[*i][k].is_zero()) {
+                Some(i) => {
+                    m.swap(k, i);
+                    neg = !neg;
+                }
+                None => return Poly::zero(r),
+            }
+        }
+        for i in k + 1..n {
+            for j in k + 1..n {
+                let e = m[i][j].clone() * m[k][k].clone() - m[i][k].clone() * m[k][j].clone();
+                m[i][j] = e.div_exact(&prev).expect("Bareiss");
+            }
+        }
+        prev = m[k][k].clone();
+    }
+    let d = m[n - 1][n - 1].clone();
+    if neg {
+        -d
+    } else {
+        d
+    }
+}
+
+// 主変数で内容と原始的部分に分けて, 原始的部分には Yun のアルゴリズムを使う.
+// 内容は変数が減るので再帰する
+fn sqf_rec(f: &Poly, fs: &mut HashMap<usize, Poly>) -> Result<(), CoefOverflow> {
+    let v = match f.get_vars().into_iter().max() {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    sqf_rec(&f.content_in(v)?, fs)?;
+    let f = f.primitive_part_in(v)?;
+    let df = f.diff(v);
+    let a = f.gcd(&df)?;
+    let mut b = f.div_exact(&a).expect("gcd divides");
+    let c = df.div_exact(&a).expect("gcd divides");
+    let mut d = c - b.diff(v);
+    let mut i = 1;
+    while !b.get_vars().is_empty() {
+        let a = b.gcd(&d)?;
+        let nb = b.div_exact(&a).expect("gcd divides");
+        d = d.div_exact(&a).expect("gcd divides") - nb.diff(v);
+        b = nb;
+        if !a.get_vars().is_empty() {
+            let r = a.r.clone();
+            let e = fs.entry(i).or_insert_with(|| Poly::one(&r));
+            *e = e.clone() * a;
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+#[test]
+fn check_content() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let px = Poly::from((x, &r));
+    let c = |n, d| Poly::from((C::new(n, d), &r));
+    // -2/3 x + 4/9 = -2/9 (3x - 2)
+    let p = c(-2, 3) * px.clone() + c(4, 9);
+    assert!(p.content() == Ok(C::new(-2, 9)));
+    assert!(p.primitive_part() == c(3, 1) * px.clone() - c(2, 1));
+    assert!(Poly::zero(&r).primitive_part() == Poly::zero(&r));
+    // 分母の lcm は i64 に収まらないが, 原始的部分の係数は収まる
+    let p = c(1, 1_000_000_007) * px.pow(2) + c(1, 998_244_353) * px.clone() + c(1, 1_000_003);
+    assert!(p.content() == Err(CoefOverflow));
+    let q = p.try_primitive_part().unwrap();
+    assert!(q.mons.iter().all(|m| m.0.coef.is_integer()));
+    assert!(p.gcd(&px) == Ok(c(1, 1)));
+}
+
+#[test]
+fn check_gcd() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let z = r.borrow_mut().vextend("z".to_string());
+    let (px, py, pz) = (
+        Poly::from((x, &r)),
+        Poly::from((y, &r)),
+        Poly::from((z, &r)),
+    );
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    // 共通因子 (x + y)(yz - 1)
+    let g = (px.clone() + py.clone()) * (py.clone() * pz.clone() - c(1));
+    let a = g.clone() * (px.pow(2) - pz.clone()) * c(6);
+    let b = g.clone() * (px.clone() * py.clone() + c(3)).pow(2) * c(-4);
+    let h = a.gcd(&b).unwrap();
+    println!("gcd = {:?}", h);
+    assert!(h == g.primitive_part());
+    assert!(a.div_exact(&h).is_some() && b.div_exact(&h).is_some());
+    // 互いに素
+    assert!(px.gcd(&(py.clone() + c(1))).unwrap() == c(1));
+    // 定数は単元
+    assert!(c(6).gcd(&c(4)).unwrap() == c(1));
+    assert!(Poly::zero(&r).gcd(&(c(2) * px.clone())).unwrap() == px);
+    // lcm
+    let l = (px.clone() * py.clone())
+        .lcm(&(py.clone() * pz.clone()))
+        .unwrap();
+    assert!(l == px.clone() * py.clone() * pz.clone());
+    // 割り切れない
+    assert!(px.div_exact(&py).is_none());
+    assert!((px.pow(2) + c(1)).div_exact(&(px.clone() + c(1))).is_none());
+}
+
+#[test]
+fn check_square_free() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    // 3 (x + 1)^2 (x - y)^3 y
+    let f1 = px.clone() + c(1);
+    let f3 = px.clone() - py.clone();
+    let p = c(3) * f1.pow(2) * f3.pow(3) * py.clone();
+    let (k, fs) = p.square_free().unwrap();
+    println!("{:?} = {:?} * {:?}", p, k, fs);
+    let back = fs
+        .iter()
+        .fold(Poly::from((k, &r)), |acc, (f, i)| acc * f.pow(*i));
+    assert!(back == p);
+    assert!(fs.iter().map(|(_, i)| *i).collect::<Vec<usize>>() == vec![1, 2, 3]);
+    assert!(fs[1].0 == f1);
+    assert!(fs[2].0 == f3.primitive_part());
+    assert!(p.square_free_part().unwrap() == (f1 * f3 * py).primitive_part());
+}
+
+#[test]
+fn check_resultant() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let z = r.borrow_mut().vextend("z".to_string());
+    let (px, py, pz) = (
+        Poly::from((x, &r)),
+        Poly::from((y, &r)),
+        Poly::from((z, &r)),
+    );
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    // res_x(x^2 - y, x - z) = z^2 - y
+    let a = px.pow(2) - py.clone();
+    let b = px.clone() - pz.clone();
+    let res = a.resultant(&b, x).unwrap();
+    println!("{:?}", res);
+    assert!(res == pz.pow(2) - py.clone());
+    assert!(res == a.resultant_sylvester(&b, x));
+    // t を消去すると y^2 - x^3
+    let t = r.borrow_mut().vextend("t".to_string());
+    let pt = Poly::from((t, &r));
+    let a = px.clone() - pt.pow(2);
+    let b = py.clone() - pt.pow(3);
+    let res = a.resultant(&b, t).unwrap();
+    println!("{:?}", res);
+    assert!(res.primitive_part() == (py.pow(2) - px.pow(3)).primitive_part());
+    assert!(res == a.resultant_sylvester(&b, t));
+    // 次数の差が大きいもの, 有理数係数, 順序を入れ替えたとき
+    let a =
+        Poly::from((C::new(1, 2), &r)) * px.pow(4) * py.clone() - px.clone() * pz.clone() + c(3);
+    let b = c(2) * px.pow(2) - py.clone() * pz.clone() * px.clone() + pz.clone();
+    let res = a.resultant(&b, x).unwrap();
+    println!("{:?}", res);
+    assert!(res == a.resultant_sylvester(&b, x));
+    assert!(res == b.resultant(&a, x).unwrap());
+    // 共通根があれば 0
+    let a = (px.clone() - py.clone()) * (px.clone() + c(1));
+    let b = (px.clone() - py.clone()) * pz.clone();
+    assert!(a.resultant(&b, x).unwrap().is_zero());
+    // 定数との終結式
+    assert!(a.resultant(&c(3), x).unwrap() == c(9));
+    // 結果が i64 に収まらなければ panic せずにエラー. (2^33)^2 = 2^66
+    let a = px.clone() - c(1 << 33);
+    assert!(a.resultant(&px.pow(2), x) == Err(CoefOverflow));
+}
@@ -0,0 +1,474 @@
+use super::coef::*;
+use super::mon::*;
+use super::poly::*;
+use super::ring::*;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, ToPrimitive, Zero};
+use std::cmp::{Ordering, Reverse};
+use std::collections::HashMap;
+
+/*
+Monomial Orders
+*/
+// 変数は番号の小さいほうが大きい
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MonOrder {
+    Lex,
+    Grevlex,
+    // 消去する変数の全次数で先に比べて, 同じなら Grevlex
+    Elim(Vec<Var>),
+}
+
+type Exps = Vec<usize>;
+
+fn grevlex(a: &[usize], b: &[usize]) -> Ordering {
+    let (da, db): (usize, usize) = (a.iter().sum(), b.iter().sum());
+    if da != db {
+        return da.cmp(&db);
+    }
+    for (x, y) in a.iter().zip(b).rev() {
+        if x != y {
+            return y.cmp(x);
+        }
+    }
+    Ordering::Equal
+}
+
+impl MonOrder {
+    fn cmp(&self, a: &[usize], b: &[usize]) -> Ordering {
+        match self {
+            MonOrder::Lex => a.cmp(b),
+            MonOrder::Grevlex => grevlex(a, b),
+            MonOrder::Elim(vs) => {
+                let d = |e: &[usize]| vs.iter().map(|v| e[v.id]).sum::<usize>();
+                d(a).cmp(&d(b)).then_with(|| grevlex(a, b))
+            }
+        }
+    }
+}
+
+/*
+Polynomials for Groebner Bases
+*/
+// 計算の途中は係数が膨らむので多倍長の有理数で持つ. 項は順序の大きい順
+
+#[derive(Clone, Debug)]
+struct GPoly {
+    terms: Vec<(Exps, BigRational)>,
+}
+
+fn divides(a: &[usize], b: &[usize]) -> bool {
+    a.iter().zip(b).all(|(x, y)| x <= y)
+}
+fn lcm_exps(a: &[usize], b: &[usize]) -> Exps {
+    a.iter().zip(b).map(|(x, y)| *x.max(y)).collect()
+}
+fn sub_exps(a: &[usize], b: &[usize]) -> Exps {
+    a.iter().zip(b).map(|(x, y)| x - y).collect()
+}
+
+impl GPoly {
+    fn from_poly(p: &Poly, n: usize, ord: &MonOrder) -> GPoly {
+        GPoly::from_poly_renamed(p, n, ord, &HashMap::new())
+    }
+    // ren にある変数は, 環の外の番号の補助変数に置き換える
+    fn from_poly_renamed(p: &Poly, n: usize, ord: &MonOrder, ren: &HashMap<Var, usize>) -> GPoly {
+        let mut terms = vec![];
+        for Reverse(m) in &p.mons {
+            if m.coef.is_zero() {
+                continue;
+            }
+            let mut e = vec![0; n];
+            for (v, d) in &m.vars {
+                e[*ren.get(v).unwrap_or(&v.id)] = *d;
+            }
+            let c = BigRational::new(BigInt::from(*m.coef.numer()), BigInt::from(*m.coef.denom()));
+            terms.push((e, c));
+        }
+        terms.sort_by(|a, b| ord.cmp(&b.0, &a.0));
+        GPoly { terms }
+    }
+    // 分母を払って整数の原始的な多項式にしてから戻す. i64 に収まらなければ CoefOverflow
+    fn to_poly(&self, r: &RingRef) -> Result<Poly, CoefOverflow> {
+        let l = self.terms.iter().fold(BigInt::one(), |l, (_, c)| {
+            num_integer::lcm(l, c.denom().clone())
+        });
+        let ns: Vec<BigInt> = self
+            .terms
+            .iter()
+            .map(|(_, c)| (c * &l).to_integer())
+            .collect();
+        let g = ns
+            .iter()
+            .fold(BigInt::zero(), |g, n| num_integer::gcd(g, n.clone()));
+        let mut mons = vec![];
+        for ((e, _), n) in self.terms.iter().zip(&ns) {
+            let vars: HashMap<Var, usize> = e
+                .iter()
+                .enumerate()
+                .filter(|(_, d)| **d > 0)
+                .map(|(i, d)| (Var::new(i), *d))
+                .collect();
+            let mut m: Mon<C> = Mon::from((vars, r));
+            m.coef = C::from_integer((n / &g).to_i64().ok_or(CoefOverflow)?);
+            mons.push(m);
+        }
+        Ok(Poly::from((mons, r)))
+    }
+    fn is_zero(&self) -> bool {
+        self.terms.is_empty()
+    }
+    fn lm(&self) -> &Exps {
+        &self.terms[0].0
+    }
+    fn monic(mut self) -> GPoly {
+        if let Some((_, c)) = self.terms.first() {
+            let c = c.clone();
+            for t in &mut self.terms {
+                t.1 /= &c;
+            }
+        }
+        self
+    }
+    // self - c * x^e * g
+    fn sub_mul(&self, c: &BigRational, e: &[usize], g: &GPoly, ord: &MonOrder) -> GPoly {
+        let shifted = g
+            .terms
+            .iter()
+            .map(|(f, d)| (f.iter().zip(e).map(|(x, y)| x + y).collect::<Exps>(), d * c));
+        let mut terms = Vec::with_capacity(self.terms.len() + g.terms.len());
+        let mut l = self.terms.iter().cloned().peekable();
+        let mut r = shifted.peekable();
+        loop {
+            let o = match (l.peek(), r.peek()) {
+                (Some(a), Some(b)) => ord.cmp(&a.0, &b.0),
+                (Some(_), None) => Ordering::Greater,
+                (None, Some(_)) => Ordering::Less,
+                (None, None) => break,
+            };
+            match o {
+                Ordering::Greater => terms.push(l.next().unwrap()),
+                Ordering::Less => {
+                    let (f, d) = r.next().unwrap();
+                    terms.push((f, -d));
+                }
+                Ordering::Equal => {
+                    let (f, a) = l.next().unwrap();
+                    let (_, b) = r.next().unwrap();
+                    let d = a - b;
+                    if !d.is_zero() {
+                        terms.push((f, d));
+                    }
+                }
+            }
+        }
+        GPoly { terms }
+    }
+    // gs で割り切れる項がなくなるまで簡約する
+    fn reduce(&self, gs: &[GPoly], ord: &MonOrder) -> GPoly {
+        let mut p = self.clone();
+        let mut rem = vec![];
+        while let Some((e, c)) = p.terms.first().cloned() {
+            match gs.iter().find(|g| divides(g.lm(), &e)) {
+                Some(g) => {
+                    let k = c / &g.terms[0].1;
+                    p = p.sub_mul(&k, &sub_exps(&e, g.lm()), g, ord);
+                }
+                None => {
+                    rem.push((e, c));
+                    p.terms.remove(0);
+                }
+            }
+        }
+        GPoly { terms: rem }
+    }
+    fn s_poly(&self, g: &GPoly, ord: &MonOrder) -> GPoly {
+        let l = lcm_exps(self.lm(), g.lm());
+        let zero = GPoly { terms: vec![] };
+        let a = zero.sub_mul(
+            &-(BigRational::one() / &self.terms[0].1),
+            &sub_exps(&l, self.lm()),
+            self,
+            ord,
+        );
+        a.sub_mul(
+            &(BigRational::one() / &g.terms[0].1),
+            &sub_exps(&l, g.lm()),
+            g,
+            ord,
+        )
+    }
+}
+
+/*
+Buchberger
+*/
+
+// 簡約された Groebner 基底. 各元は先頭係数 1
+fn buchberger(mut gs: Vec<GPoly>, ord: &MonOrder) -> Vec<GPoly> {
+    gs.retain(|g| !g.is_zero());
+    let mut pairs: Vec<(usize, usize)> = vec![];
+    for j in 0..gs.len() {
+        for i in 0..j {
+            pairs.push((i, j));
+        }
+    }
+    while !pairs.is_empty() {
+        // lcm が一番小さい組から (normal strategy)
+        let k = (0..pairs.len())
+            .min_by(|a, b| {
+                let (i, j) = pairs[*a];
+                let (k, l) = pairs[*b];
+                ord.cmp(
+                    &lcm_exps(gs[i].lm(), gs[j].lm()),
+                    &lcm_exps(gs[k].lm(), gs[l].lm()),
+                )
+            })
+            .unwrap();
+        let (i, j) = pairs.swap_remove(k);
+        let (li, lj) = (gs[i].lm(), gs[j].lm());
+        // 先頭項が互いに素なら 0 に簡約される
+        if li.iter().zip(lj).all(|(a, b)| *a == 0 || *b == 0) {
+            continue;
+        }
+        let h = gs[i].s_poly(&gs[j], ord).reduce(&gs, ord);
+        if !h.is_zero() {
+            let n = gs.len();
+            gs.push(h.monic());
+            pairs.extend((0..n).map(|i| (i, n)));
+        }
+    }
+    // 先頭項が他で割り切れるものを除いてから, 互いに簡約する
+    let mut min: Vec<GPoly> = vec![];
+    for (i, g) in gs.iter().enumerate() {
+        let dup = gs
+            .iter()
+            .enumerate()
+            .any(|(j, h)| j != i && divides(h.lm(), g.lm()) && (h.lm() != g.lm() || j < i));
+        if !dup {
+            min.push(g.clone().monic());
+        }
+    }
+    let mut res: Vec<GPoly> = (0..min.len())
+        .map(|i| {
+            let others: Vec<GPoly> = min
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, g)| g.clone())
+                .collect();
+            let mut g = min[i].clone();
+            let head = g.terms.remove(0);
+            let mut t = g.reduce(&others, ord);
+            t.terms.insert(0, head);
+            t
+        })
+        .collect();
+    res.sort_by(|a, b| ord.cmp(a.lm(), b.lm()));
+    res
+}
+
+fn ring_size(ps: &[Poly]) -> Option<(RingRef, usize)> {
+    let r = ps.first()?.r.clone();
+    for p in ps {
+        r.assert_same(&p.r);
+    }
+    let n = r.borrow().var_list().len();
+    Some((r, n))
+}
+
+// 簡約された Groebner 基底. 各元は整数係数で原始的にして, 先頭項の小さい順に並べる
+pub fn groebner(ps: &[Poly], ord: &MonOrder) -> Result<Vec<Poly>, CoefOverflow> {
+    let (r, n) = match ring_size(ps) {
+        Some(rn) => rn,
+        None => return Ok(vec![]),
+    };
+    let gs = ps.iter().map(|p| GPoly::from_poly(p, n, ord)).collect();
+    buchberger(gs, ord).iter().map(|g| g.to_poly(&r)).collect()
+}
+
+// gs が ord についての Groebner 基底なら, 正規形になる
+pub fn normal_form(p: &Poly, gs: &[Poly], ord: &MonOrder) -> Result<Poly, CoefOverflow> {
+    let n = p.r.borrow().var_list().len();
+    let gs: Vec<GPoly> = gs.iter().map(|g| GPoly::from_poly(g, n, ord)).collect();
+    let f = GPoly::from_poly(p, n, ord).reduce(&gs, ord);
+    // 正規形は定数倍を変えずに返す
+    let mut mons = vec![];
+    for (e, c) in f.terms {
+        let vars: HashMap<Var, usize> = e
+            .iter()
+            .enumerate()
+            .filter(|(_, d)| **d > 0)
+            .map(|(i, d)| (Var::new(i), *d))
+            .collect();
+        let mut m: Mon<C> = Mon::from((vars, &p.r));
+        m.coef = match (c.numer().to_i64(), c.denom().to_i64()) {
+            (Some(n), Some(d)) => C::new(n, d),
+            _ => return Err(CoefOverflow),
+        };
+        mons.push(m);
+    }
+    Ok(Poly::from((mons, &p.r)))
+}
+
+// ps の生成するイデアルと, vs を含まない多項式環との共通部分
+// ループカウンタや入力の初期値を消して, プログラム変数だけの不変式にするのに使う
+pub fn eliminate(ps: &[Poly], vs: &[Var]) -> Result<Vec<Poly>, CoefOverflow> {
+    let ord = MonOrder::Elim(vs.to_vec());
+    Ok(groebner(ps, &ord)?
+        .into_iter()
+        .filter(|g| g.get_vars().iter().all(|v| !vs.contains(v)))
+        .collect())
+}
+
+// 環にない補助変数 t を一つ足して, a t + b の形の生成元から t を消去する.
+// 共通部分や飽和に使う. 補助変数は Ring に登録しない
+pub(crate) fn eliminate_aux(gens: &[(Poly, Poly)], r: &RingRef) -> Result<Vec<Poly>, CoefOverflow> {
+    let n = r.borrow().var_list().len();
+    let ord = MonOrder::Elim(vec![Var::new(n)]);
+    let mut gs = vec![];
+    for (a, b) in gens {
+        r.assert_same(&a.r);
+        r.assert_same(&b.r);
+        let mut at = GPoly::from_poly(a, n + 1, &ord);
+        // 単項式を掛けても順序は変わらない
+        for t in &mut at.terms {
+            t.0[n] += 1;
+        }
+        let b = GPoly::from_poly(b, n + 1, &ord);
+        gs.push(b.sub_mul(&-BigRational::one(), &vec![0; n + 1], &at, &ord));
+    }
+    buchberger(gs, &ord)
+        .iter()
+        .filter(|g| g.lm()[n] == 0)
+        .map(|g| g.to_poly(r))
+        .collect()
+}
+
+// 並行代入 xs := ps の後で成り立つ多項式. 代入前の xs を補助変数に置いて,
+// gens[xs -> olds] と xs - ps[xs -> olds] から olds を消去する
+pub(crate) fn eliminate_old(
+    gens: &[Poly],
+    asg: &[(Var, Poly)],
+    r: &RingRef,
+) -> Result<Vec<Poly>, CoefOverflow> {
+    let n = r.borrow().var_list().len();
+    let m = n + asg.len();
+    let ren: HashMap<Var, usize> = asg
+        .iter()
+        .enumerate()
+        .map(|(i, (x, _))| (*x, n + i))
+        .collect();
+    let ord = MonOrder::Elim((n..m).map(Var::new).collect());
+    let mut gs: Vec<GPoly> = gens
+        .iter()
+        .map(|g| GPoly::from_poly_renamed(g, m, &ord, &ren))
+        .collect();
+    for (x, p) in asg {
+        r.assert_same(&p.r);
+        let x = GPoly::from_poly(&Poly::from((*x, r)), m, &ord);
+        let p = GPoly::from_poly_renamed(p, m, &ord, &ren);
+        gs.push(x.sub_mul(&BigRational::one(), &vec![0; m], &p, &ord));
+    }
+    buchberger(gs, &ord)
+        .iter()
+        .filter(|g| g.lm()[n..].iter().all(|d| *d == 0))
+        .map(|g| g.to_poly(r))
+        .collect()
+}
+
+// 環の変数より大きい番号の変数を補助変数とみて消去する
+pub(crate) fn eliminate_beyond(gens: &[Poly], r: &RingRef) -> Result<Vec<Poly>, CoefOverflow> {
+    let n = r.borrow().var_list().len();
+    let m = gens
+        .iter()
+        .flat_map(|g| g.get_vars())
+        .map(|v| v.id + 1)
+        .fold(n, usize::max);
+    let ord = MonOrder::Elim((n..m).map(Var::new).collect());
+    let gs = gens.iter().map(|g| GPoly::from_poly(g, m, &ord)).collect();
+    buchberger(gs, &ord)
+        .iter()
+        .filter(|g| g.lm()[n..].iter().all(|d| *d == 0))
+        .map(|g| g.to_poly(r))
+        .collect()
+}
+
+#[test]
+fn check_groebner() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    // x^2 + y^2 - 1, x - y
+    let ps = vec![px.pow(2) + py.pow(2) - c(1), px.clone() - py.clone()];
+    let gb = groebner(&ps, &MonOrder::Lex).unwrap();
+    println!("{:?}", gb);
+    assert!(gb == vec![c(2) * py.pow(2) - c(1), px.clone() - py.clone()]);
+    // 元の生成元は正規形が 0
+    for p in &ps {
+        assert!(normal_form(p, &gb, &MonOrder::Lex).unwrap().is_zero());
+    }
+    assert!(
+        normal_form(&px.pow(2), &gb, &MonOrder::Lex).unwrap() == Poly::from((C::new(1, 2), &r))
+    );
+    // Grevlex でも同じイデアル
+    let gb = groebner(&ps, &MonOrder::Grevlex).unwrap();
+    println!("{:?}", gb);
+    for p in &ps {
+        assert!(normal_form(p, &gb, &MonOrder::Grevlex).unwrap().is_zero());
+    }
+    assert!(!normal_form(&px, &gb, &MonOrder::Grevlex).unwrap().is_zero());
+    // 単位イデアル
+    let gb = groebner(&[px.clone(), px.clone() + c(1)], &MonOrder::Grevlex).unwrap();
+    assert!(gb == vec![c(1)]);
+    // 基底や正規形の係数が i64 に収まらなければエラー. y - x^2 は y - 2^80 になる
+    let big = px.clone() - c(1 << 40);
+    let ps = vec![big.clone(), py.clone() - px.pow(2)];
+    assert!(groebner(&ps, &MonOrder::Grevlex) == Err(CoefOverflow));
+    assert!(normal_form(&px.pow(2), &[big], &MonOrder::Grevlex) == Err(CoefOverflow));
+}
+
+#[test]
+fn check_eliminate() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let t = r.borrow_mut().vextend("t".to_string());
+    let (px, py, pt) = (
+        Poly::from((x, &r)),
+        Poly::from((y, &r)),
+        Poly::from((t, &r)),
+    );
+    // x = t^2, y = t^3 から t を消去すると y^2 - x^3
+    let es = eliminate(&[px.clone() - pt.pow(2), py.clone() - pt.pow(3)], &[t]).unwrap();
+    println!("{:?}", es);
+    assert!(es == vec![(py.pow(2) - px.pow(3)).primitive_part()]);
+    // 自由落下: v = v0 - g t0, x = x0 + v0 t0 - 1/2 g t0^2 から t0 を消去
+    let v = r.borrow_mut().vextend("v".to_string());
+    let v0 = r.borrow_mut().vextend("v0".to_string());
+    let x0 = r.borrow_mut().vextend("x0".to_string());
+    let g = r.borrow_mut().vextend("g".to_string());
+    let t0 = r.borrow_mut().vextend("t0".to_string());
+    let p = |v| Poly::from((v, &r));
+    let half = Poly::from((C::new(1, 2), &r));
+    let ps = vec![
+        p(v) - p(v0) + p(g) * p(t0),
+        px.clone() - p(x0) - p(v0) * p(t0) + half * p(g) * p(t0).pow(2),
+    ];
+    let es = eliminate(&ps, &[t0]).unwrap();
+    println!("{:?}", es);
+    // 2 g (x - x0) = v0^2 - v^2
+    let inv =
+        Poly::from((C::new(2, 1), &r)) * p(g) * (px.clone() - p(x0)) - p(v0).pow(2) + p(v).pow(2);
+    let gb = groebner(&es, &MonOrder::Grevlex).unwrap();
+    assert!(normal_form(&inv, &gb, &MonOrder::Grevlex)
+        .unwrap()
+        .is_zero());
+    assert!(es.iter().all(|e| !e.get_vars().contains(&t0)));
+    // 何も消えなければそのまま
+    assert!(eliminate(&[px.clone() - py.clone()], &[t]).unwrap() == vec![px - py]);
+}
@@ -0,0 +1,219 @@
+use super::coef::*;
+use super::ideal::*;
+#[allow(unused_imports)]
+use super::poly::*;
+#[allow(unused_imports)]
+use super::ring::*;
+
+/*
+Hilbert Series
+*/
+// k[x] / I の Hilbert 級数 num(t) / (1 - t)^dim. 変数は環の変数全部
+// Grevlex の先頭項イデアルで数えるので, 次数 s の係数は次数 s の標準単項式の数
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HilbertSeries {
+    // num[i] が t^i の係数. num(1) != 0 になるまで (1 - t) で割ってある
+    pub num: Vec<i64>,
+    pub dim: usize,
+}
+
+impl std::fmt::Display for HilbertSeries {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut res = String::new();
+        for (i, c) in self.num.iter().enumerate() {
+            if *c == 0 {
+                continue;
+            }
+            let t = match i {
+                0 => String::new(),
+                1 => "t".to_string(),
+                _ => format!("t^{}", i),
+            };
+            let a = c.abs();
+            let a = if a == 1 && i > 0 {
+                String::new()
+            } else {
+                a.to_string()
+            };
+            let sign = if *c < 0 {
+                "-"
+            } else if res.is_empty() {
+                ""
+            } else {
+                "+"
+            };
+            res = format!("{}{}{}{}", res, sign, a, t);
+        }
+        if res.is_empty() {
+            res = "0".to_string();
+        }
+        match self.dim {
+            0 => write!(f, "{}", res),
+            1 => write!(f, "({})/(1-t)", res),
+            d => write!(f, "({})/(1-t)^{}", res, d),
+        }
+    }
+}
+
+impl HilbertSeries {
+    // 次数 s の係数. num(t) の各項に 1/(1-t)^dim の係数 C(s-i+dim-1, dim-1) を掛けて足す
+    pub fn coeff(&self, s: usize) -> i64 {
+        let mut res = 0;
+        for (i, c) in self.num.iter().enumerate() {
+            if i > s {
+                break;
+            }
+            res += c * match self.dim {
+                0 if i == s => 1,
+                0 => 0,
+                d => binom(s - i + d - 1, d - 1),
+            };
+        }
+        res
+    }
+    // 多様体の次数. num(1)
+    pub fn degree(&self) -> i64 {
+        self.num.iter().sum()
+    }
+}
+
+fn binom(n: usize, k: usize) -> i64 {
+    (0..k).fold(1, |acc, i| acc * (n - i) as i64 / (i as i64 + 1))
+}
+
+type Exps = Vec<usize>;
+
+// 単項式イデアル <ms> の k[x] / <ms> の級数の分子 (分母は (1 - t)^n)
+// N(<m1..mk>) = N(<m1..mk-1>) - t^deg(mk) N(<m1..mk-1> : mk)
+fn numerator(ms: &[Exps]) -> Vec<i64> {
+    let ms = minimize(ms.to_vec());
+    let (last, rest) = match ms.split_last() {
+        Some(lr) => lr,
+        None => return vec![1],
+    };
+    let mut res = numerator(rest);
+    let colon: Vec<Exps> = rest
+        .iter()
+        .map(|m| {
+            m.iter()
+                .zip(last)
+                .map(|(a, b)| a.saturating_sub(*b))
+                .collect()
+        })
+        .collect();
+    let d: usize = last.iter().sum();
+    let q = numerator(&colon);
+    if res.len() < q.len() + d {
+        res.resize(q.len() + d, 0);
+    }
+    for (i, c) in q.iter().enumerate() {
+        res[i + d] -= c;
+    }
+    res
+}
+
+// 他で割り切れる生成元を除く
+fn minimize(mut ms: Vec<Exps>) -> Vec<Exps> {
+    ms.sort_by_key(|m| m.iter().sum::<usize>());
+    ms.dedup();
+    let mut res: Vec<Exps> = vec![];
+    for m in ms {
+        if !res.iter().any(|n| n.iter().zip(&m).all(|(a, b)| a <= b)) {
+            res.push(m);
+        }
+    }
+    res
+}
+
+impl Ideal {
+    pub fn hilbert_series(&self) -> Result<HilbertSeries, CoefOverflow> {
+        let vs = self.r.borrow().var_list();
+        let ms: Vec<Exps> = self
+            .gb()?
+            .iter()
+            .map(|g| {
+                let lm = &g.mons[0].0.vars;
+                vs.iter().map(|v| *lm.get(v).unwrap_or(&0)).collect()
+            })
+            .collect();
+        let mut num = numerator(&ms);
+        let mut dim = vs.len();
+        // num(1) = 0 の間は (1 - t) で割る
+        while dim > 0 && !num.is_empty() && num.iter().sum::<i64>() == 0 {
+            let mut q = vec![0; num.len() - 1];
+            let mut acc = 0;
+            for (i, c) in num.iter().take(num.len() - 1).enumerate() {
+                acc += c;
+                q[i] = acc;
+            }
+            num = q;
+            dim -= 1;
+        }
+        while num.last() == Some(&0) {
+            num.pop();
+        }
+        Ok(HilbertSeries { num, dim })
+    }
+    // 次数 s の標準単項式の数
+    pub fn hilbert_function(&self, s: usize) -> Result<i64, CoefOverflow> {
+        Ok(self.hilbert_series()?.coeff(s))
+    }
+    // 零点集合の次元. 零点がなければ None
+    pub fn dim(&self) -> Result<Option<usize>, CoefOverflow> {
+        if self.is_one()? {
+            Ok(None)
+        } else {
+            Ok(Some(self.hilbert_series()?.dim))
+        }
+    }
+}
+
+#[test]
+fn check_hilbert() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let z = r.borrow_mut().vextend("z".to_string());
+    let (px, py, pz) = (
+        Poly::from((x, &r)),
+        Poly::from((y, &r)),
+        Poly::from((z, &r)),
+    );
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    let i = |gs: Vec<Poly>| Ideal::new(gs, &r);
+    // 全空間: 1/(1-t)^3
+    let h = Ideal::zero(&r).hilbert_series().unwrap();
+    println!("{}", h);
+    assert!(
+        h == HilbertSeries {
+            num: vec![1],
+            dim: 3
+        }
+    );
+    assert!(h.coeff(2) == 6);
+    // 曲面 x^2 + y^2 + z^2 = 1: (1+t)/(1-t)^2
+    let sphere = i(vec![px.pow(2) + py.pow(2) + pz.pow(2) - c(1)]);
+    let h = sphere.hilbert_series().unwrap();
+    println!("{}", h);
+    assert!(
+        h == HilbertSeries {
+            num: vec![1, 1],
+            dim: 2
+        }
+    );
+    assert!(format!("{}", h) == "(1+t)/(1-t)^2");
+    assert!(sphere.dim() == Ok(Some(2)) && h.degree() == 2);
+    assert!(sphere.hilbert_function(3) == Ok(7));
+    // ねじれ三次曲線 y = x^2, z = x^3 は次元 1
+    let cubic = i(vec![py.clone() - px.pow(2), pz.clone() - px.pow(3)]);
+    println!("{}", cubic.hilbert_series().unwrap());
+    assert!(cubic.dim() == Ok(Some(1)));
+    // 有限個の点: x^2 = 1, y = z = 0 は次元 0, 次数 2
+    let pts = i(vec![px.pow(2) - c(1), py.clone(), pz.clone()]);
+    let h = pts.hilbert_series().unwrap();
+    println!("{}", h);
+    assert!(h.dim == 0 && h.degree() == 2);
+    // 空集合
+    assert!(i(vec![px.clone(), px.clone() - c(1)]).dim() == Ok(None));
+}
@@ -0,0 +1,369 @@
+use super::coef::*;
+use super::groebner::*;
+use super::poly::*;
+use super::ring::*;
+use std::collections::HashMap;
+
+/*
+Ideals of Polynomials
+*/
+// 係数が定数の多項式で生成されるイデアル. 比較は簡約された Groebner 基底で行う.
+// Groebner 基底の係数が i64 に収まらなければ, 判定も演算も CoefOverflow を返す
+
+#[derive(Clone)]
+pub struct Ideal {
+    pub gens: Vec<Poly>,
+    pub r: RingRef,
+}
+
+impl std::fmt::Debug for Ideal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<")?;
+        for (i, g) in self.gens.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:?}", g)?;
+        }
+        write!(f, ">")
+    }
+}
+
+impl Ideal {
+    pub fn new(gens: Vec<Poly>, r: &RingRef) -> Ideal {
+        for g in &gens {
+            r.assert_same(&g.r);
+        }
+        let gens = gens.into_iter().filter(|g| !g.is_zero()).collect();
+        Ideal { gens, r: r.clone() }
+    }
+    pub fn zero(r: &RingRef) -> Ideal {
+        Ideal::new(vec![], r)
+    }
+    // 環全体
+    pub fn one(r: &RingRef) -> Ideal {
+        Ideal::new(vec![Poly::one(r)], r)
+    }
+}
+
+impl From<(Vec<Poly>, &RingRef)> for Ideal {
+    fn from(gr: (Vec<Poly>, &RingRef)) -> Self {
+        Ideal::new(gr.0, gr.1)
+    }
+}
+
+// 判定
+impl Ideal {
+    // Grevlex についての簡約された Groebner 基底
+    pub fn gb(&self) -> Result<Vec<Poly>, CoefOverflow> {
+        groebner(&self.gens, &MonOrder::Grevlex)
+    }
+    pub fn reduce(&self, p: &Poly) -> Result<Poly, CoefOverflow> {
+        normal_form(p, &self.gb()?, &MonOrder::Grevlex)
+    }
+    pub fn contains(&self, p: &Poly) -> Result<bool, CoefOverflow> {
+        self.r.assert_same(&p.r);
+        Ok(self.reduce(p)?.is_zero())
+    }
+    pub fn is_subset(&self, other: &Ideal) -> Result<bool, CoefOverflow> {
+        let gb = other.gb()?;
+        for g in &self.gens {
+            if !normal_form(g, &gb, &MonOrder::Grevlex)?.is_zero() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+    pub fn is_zero(&self) -> bool {
+        self.gens.is_empty()
+    }
+    pub fn is_one(&self) -> Result<bool, CoefOverflow> {
+        self.contains(&Poly::one(&self.r))
+    }
+    // 同じイデアルか. 失敗しうるので PartialEq にはしない
+    pub fn same(&self, other: &Ideal) -> Result<bool, CoefOverflow> {
+        self.r.assert_same(&other.r);
+        Ok(self.gb()? == other.gb()?)
+    }
+}
+
+// 演算
+impl Ideal {
+    pub fn sum(&self, other: &Ideal) -> Ideal {
+        self.r.assert_same(&other.r);
+        let mut gens = self.gens.clone();
+        gens.extend(other.gens.iter().cloned());
+        Ideal::new(gens, &self.r)
+    }
+    pub fn product(&self, other: &Ideal) -> Ideal {
+        self.r.assert_same(&other.r);
+        let mut gens = vec![];
+        for f in &self.gens {
+            for g in &other.gens {
+                gens.push(f.clone() * g.clone());
+            }
+        }
+        Ideal::new(gens, &self.r)
+    }
+    // t I + (1 - t) J から t を消去する
+    pub fn intersection(&self, other: &Ideal) -> Result<Ideal, CoefOverflow> {
+        self.r.assert_same(&other.r);
+        let zero = Poly::zero(&self.r);
+        let mut gens: Vec<(Poly, Poly)> = self
+            .gens
+            .iter()
+            .map(|f| (f.clone(), zero.clone()))
+            .collect();
+        gens.extend(other.gens.iter().map(|g| (-g.clone(), g.clone())));
+        Ok(Ideal::new(eliminate_aux(&gens, &self.r)?, &self.r))
+    }
+    // I : f = (I ∩ <f>) / f
+    fn quotient_poly(&self, f: &Poly) -> Result<Ideal, CoefOverflow> {
+        if f.is_zero() {
+            return Ok(Ideal::one(&self.r));
+        }
+        let i = self.intersection(&Ideal::new(vec![f.clone()], &self.r))?;
+        let gens = i
+            .gens
+            .iter()
+            .map(|g| g.div_exact(f).expect("element of <f>"))
+            .collect();
+        Ok(Ideal::new(gens, &self.r))
+    }
+    // I : J = { f | f J ⊆ I }
+    pub fn quotient(&self, other: &Ideal) -> Result<Ideal, CoefOverflow> {
+        self.r.assert_same(&other.r);
+        other.gens.iter().try_fold(Ideal::one(&self.r), |acc, g| {
+            acc.intersection(&self.quotient_poly(g)?)
+        })
+    }
+    // I : f^∞ = (I + <1 - t f>) ∩ k[x]
+    fn saturation_poly(&self, f: &Poly) -> Result<Ideal, CoefOverflow> {
+        let zero = Poly::zero(&self.r);
+        let mut gens: Vec<(Poly, Poly)> = self
+            .gens
+            .iter()
+            .map(|g| (zero.clone(), g.clone()))
+            .collect();
+        gens.push((-f.clone(), Poly::one(&self.r)));
+        Ok(Ideal::new(eliminate_aux(&gens, &self.r)?, &self.r))
+    }
+    // I : J^∞ = ∩ I : g^∞
+    pub fn saturation(&self, other: &Ideal) -> Result<Ideal, CoefOverflow> {
+        self.r.assert_same(&other.r);
+        other.gens.iter().try_fold(Ideal::one(&self.r), |acc, g| {
+            acc.intersection(&self.saturation_poly(g)?)
+        })
+    }
+    // vs を含まない多項式との共通部分
+    pub fn eliminate(&self, vs: &[Var]) -> Result<Ideal, CoefOverflow> {
+        Ok(Ideal::new(eliminate(&self.gens, vs)?, &self.r))
+    }
+}
+
+/*
+Varieties
+*/
+// 零点は代数閉体の中で考える. 解の列挙だけは有理数の解に限る
+
+impl Ideal {
+    // Rabinowitsch: f ∈ √I ⇔ 1 ∈ I + <1 - t f> ⇔ I : f^∞ = <1>
+    pub fn radical_contains(&self, f: &Poly) -> Result<bool, CoefOverflow> {
+        self.r.assert_same(&f.r);
+        self.saturation_poly(f)?.is_one()
+    }
+    // 弱零点定理: 零点がない ⇔ 1 ∈ I. 不変式の集合が矛盾しているかの判定に使う
+    pub fn is_empty_variety(&self) -> Result<bool, CoefOverflow> {
+        self.is_one()
+    }
+    // 生成元に現れる変数. 番号順
+    pub fn get_vars(&self) -> Vec<Var> {
+        let mut vs: Vec<Var> = self.gens.iter().flat_map(|g| g.get_vars()).collect();
+        vs.sort();
+        vs.dedup();
+        vs
+    }
+    // 現れる変数の多項式環で零点が有限個. 変数ごとに, その冪が先頭項になる基底元がある
+    pub fn is_zero_dim(&self) -> Result<bool, CoefOverflow> {
+        let gb = self.gb()?;
+        Ok(self.get_vars().iter().all(|v| {
+            gb.iter().any(|g| {
+                let lm = &g.mons[0].0.vars;
+                lm.len() == 1 && lm.contains_key(v)
+            })
+        }))
+    }
+    // 零次元なら有理数の解を全部返す. 零次元でなければ None
+    pub fn rational_solutions(&self) -> Result<Option<Vec<HashMap<Var, C>>>, CoefOverflow> {
+        if !self.is_zero_dim()? {
+            return Ok(None);
+        }
+        let gb = groebner(&self.gens, &MonOrder::Lex)?;
+        // Lex の基底は三角形になるので, 番号の大きい変数から決めていく
+        let mut vs = self.get_vars();
+        vs.reverse();
+        let mut sols = vec![];
+        solve_rec(&gb, &vs, &mut HashMap::new(), &mut sols)?;
+        Ok(Some(sols))
+    }
+}
+
+fn solve_rec(
+    gb: &[Poly],
+    vs: &[Var],
+    pt: &mut HashMap<Var, C>,
+    sols: &mut Vec<HashMap<Var, C>>,
+) -> Result<(), CoefOverflow> {
+    let (v, rest) = match vs.split_first() {
+        Some(vr) => vr,
+        None => {
+            if gb.iter().all(|g| g.eval(pt).is_zero()) {
+                sols.push(pt.clone());
+            }
+            return Ok(());
+        }
+    };
+    // 決まった変数と v だけを含む元を代入して, v の一変数多項式の gcd をとる
+    let mut u = Poly::zero(&gb[0].r);
+    for g in gb {
+        if g.get_vars().iter().any(|w| w != v && !pt.contains_key(w)) {
+            continue;
+        }
+        let q = g.partial_eval(pt);
+        if q.get_vars().is_empty() {
+            if !q.is_zero() {
+                return Ok(());
+            }
+            continue;
+        }
+        u = u.gcd(&q)?;
+    }
+    if u.is_zero() {
+        return Ok(());
+    }
+    // 一次の因子が有理数の根
+    for (f, _) in u.factor()?.1 {
+        if f.tdeg() != 1 {
+            continue;
+        }
+        let cs = f.coeffs_in(*v);
+        let none = HashMap::new();
+        let x = -cs[0].eval(&none) / cs[1].eval(&none);
+        pt.insert(*v, x);
+        solve_rec(gb, rest, pt, sols)?;
+        pt.remove(v);
+    }
+    Ok(())
+}
+
+#[test]
+fn check_ideal_ops() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    let i = |gs: Vec<Poly>| Ideal::new(gs, &r);
+    let same = |a: &Ideal, b: &Ideal| a.same(b).unwrap();
+    // 生成元が違っても同じイデアル
+    assert!(same(
+        &i(vec![px.clone(), py.clone()]),
+        &i(vec![px.clone() + py.clone(), px.clone() - py.clone()])
+    ));
+    assert!(!same(&i(vec![px.clone()]), &i(vec![py.clone()])));
+    // 和と積
+    let a = i(vec![px.clone()]);
+    let b = i(vec![py.clone()]);
+    assert!(same(&a.sum(&b), &i(vec![px.clone(), py.clone()])));
+    assert!(same(&a.product(&b), &i(vec![px.clone() * py.clone()])));
+    assert!(i(vec![px.clone()])
+        .sum(&i(vec![px.clone() + c(1)]))
+        .is_one()
+        .unwrap());
+    // <x> ∩ <y> = <xy>, <x^2> ∩ <x y> = <x^2 y>
+    let ab = a.intersection(&b).unwrap();
+    println!("{:?}", ab);
+    assert!(same(&ab, &i(vec![px.clone() * py.clone()])));
+    let p = i(vec![px.pow(2)])
+        .intersection(&i(vec![px.clone() * py.clone()]))
+        .unwrap();
+    assert!(same(&p, &i(vec![px.pow(2) * py.clone()])));
+    assert!(ab.is_subset(&a).unwrap() && ab.is_subset(&b).unwrap() && !a.is_subset(&b).unwrap());
+    // 商: <x^2 y, x y^2> : <x y> = <x, y>
+    let q = i(vec![px.pow(2) * py.clone(), px.clone() * py.pow(2)])
+        .quotient(&i(vec![px.clone() * py.clone()]))
+        .unwrap();
+    println!("{:?}", q);
+    assert!(same(&q, &i(vec![px.clone(), py.clone()])));
+    // 飽和: <x^3 y, x^2 (y - 1)> : <x>^∞ = <y, y - 1> = <1>
+    let s = i(vec![
+        px.pow(3) * py.clone(),
+        px.pow(2) * (py.clone() - c(1)),
+    ])
+    .saturation(&a)
+    .unwrap();
+    println!("{:?}", s);
+    assert!(s.is_one().unwrap());
+    // <x (y - 1)> : <x>^∞ = <y - 1>
+    let s = i(vec![px.clone() * (py.clone() - c(1))])
+        .saturation(&a)
+        .unwrap();
+    assert!(same(&s, &i(vec![py.clone() - c(1)])));
+    // 零イデアル
+    assert!(Ideal::zero(&r).is_zero() && Ideal::zero(&r).gb().unwrap().is_empty());
+    assert!(same(
+        &a.intersection(&Ideal::zero(&r)).unwrap(),
+        &Ideal::zero(&r)
+    ));
+    assert!(a.contains(&(px.clone() * py.clone())).unwrap() && !a.contains(&py).unwrap());
+    // Groebner 基底が i64 に収まらなければ, 比較も所属判定もエラー
+    let big = i(vec![px.clone() - c(1 << 40), py.clone() - px.pow(2)]);
+    assert!(big.contains(&py) == Err(CoefOverflow));
+    assert!(big.same(&a) == Err(CoefOverflow));
+}
+
+#[test]
+fn check_varieties() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    let i = |gs: Vec<Poly>| Ideal::new(gs, &r);
+    // 根基: x ∈ √<x^2>, x + 1 ∉ √<x^2>
+    let a = i(vec![px.pow(2), py.pow(3)]);
+    assert!(a.radical_contains(&px) == Ok(true));
+    assert!(a.radical_contains(&(px.clone() + py.clone())) == Ok(true));
+    assert!(a.radical_contains(&(px.clone() + c(1))) == Ok(false));
+    assert!(a.contains(&px) == Ok(false));
+    // 矛盾する不変式
+    assert!(i(vec![px.clone() * py.clone() - c(1), px.clone()]).is_empty_variety() == Ok(true));
+    assert!(a.is_empty_variety() == Ok(false));
+    // 零次元: x^2 = 1, y = 2x と x^2 = 2 (有理数解なし)
+    let b = i(vec![px.pow(2) - c(1), py.clone() - c(2) * px.clone()]);
+    assert!(b.is_zero_dim() == Ok(true));
+    let sols = b.rational_solutions().unwrap().expect("zero dimensional");
+    println!("{:?}", sols);
+    assert!(sols.len() == 2);
+    for (a, b) in [(1, 2), (-1, -2)] {
+        assert!(sols
+            .iter()
+            .any(|s| s[&x] == C::new(a, 1) && s[&y] == C::new(b, 1)));
+    }
+    let b = i(vec![px.pow(2) - c(2), py.clone() - c(1)]);
+    assert!(b.rational_solutions() == Ok(Some(vec![])));
+    // x y = 1 は零次元でない
+    assert!(i(vec![px.clone() * py.clone() - c(1)])
+        .rational_solutions()
+        .unwrap()
+        .is_none());
+    // 三次と円の交点の一部だけが有理数: (x - 1)(x^2 - 3) = 0, x^2 + y^2 = 5
+    let b = i(vec![
+        (px.clone() - c(1)) * (px.pow(2) - c(3)),
+        px.pow(2) + py.pow(2) - c(5),
+    ]);
+    let sols = b.rational_solutions().unwrap().expect("zero dimensional");
+    println!("{:?}", sols);
+    assert!(sols.len() == 2);
+    assert!(sols.iter().all(|s| s[&x] == C::one()));
+}
@@ -1,13 +1,26 @@
-mod coef;
-mod constraints;
-mod expr;
-mod expr_parse;
-mod mon;
-mod p_comb;
-mod poly;
-mod poly_parse;
-mod ring;
-mod temp;
+pub mod acsl;
+pub mod cas;
+pub mod coef;
+pub mod constraints;
+pub mod expr;
+pub mod expr_parse;
+pub mod factor;
+pub mod forward;
+pub mod gcd;
+pub mod groebner;
+pub mod hilbert;
+pub mod ideal;
+pub mod mon;
+pub mod p_comb;
+pub mod poly;
+pub mod poly_parse;
+pub mod print;
+pub mod recurrence;
+pub mod report;
+pub mod ring;
+pub mod search;
+pub mod smt;
+pub mod temp;
 #[allow(unused_imports)]
 use coef::*;
 #[allow(unused_imports)]
@@ -356,7 +369,7 @@ fn c_fall() {
         }
         None => {
             println!("Solution dosn't exist");
-            std::process::exit(0);
+            return;
         }
     }
     // orthogonal components
@@ -0,0 +1,191 @@
+use multi_var_poly::acsl::*;
+use multi_var_poly::cas::*;
+use multi_var_poly::expr::*;
+use multi_var_poly::expr_parse::*;
+use multi_var_poly::p_comb::Parser;
+use multi_var_poly::report::*;
+use multi_var_poly::ring::*;
+use multi_var_poly::search::*;
+use multi_var_poly::smt::*;
+use multi_var_poly::temp::RemMode;
+
+const USAGE: &str =
+    "usage: multi-var_poly [--deg N|auto] [--mode quotient|nf] [--json] [--smt int|real] [--acsl] [--cas singular|m2|sage] FILE";
+
+struct Opts {
+    // None なら次数を探す
+    deg: Option<usize>,
+    mode: RemMode,
+    json: bool,
+    smt: Option<Logic>,
+    acsl: bool,
+    cas: Option<Cas>,
+    file: String,
+}
+
+fn parse_args() -> Result<Opts, String> {
+    let mut opts = Opts {
+        deg: Some(2),
+        mode: RemMode::Quotient,
+        json: false,
+        smt: None,
+        acsl: false,
+        cas: None,
+        file: String::new(),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(a) = args.next() {
+        match a.as_str() {
+            "--deg" => {
+                let d = args.next().ok_or("--deg needs a value")?;
+                opts.deg = match d.as_str() {
+                    "auto" => None,
+                    _ => Some(d.parse().map_err(|_| format!("bad degree: {}", d))?),
+                };
+            }
+            "--mode" => {
+                opts.mode = match args.next().as_deref() {
+                    Some("quotient") => RemMode::Quotient,
+                    Some("nf") => RemMode::NormalForm,
+                    m => return Err(format!("bad mode: {:?}", m)),
+                }
+            }
+            "--json" => opts.json = true,
+            "--acsl" => opts.acsl = true,
+            "--cas" => {
+                opts.cas = match args.next().as_deref() {
+                    Some("singular") => Some(Cas::Singular),
+                    Some("m2") => Some(Cas::Macaulay2),
+                    Some("sage") => Some(Cas::Sage),
+                    c => return Err(format!("bad cas: {:?}", c)),
+                }
+            }
+            "--smt" => {
+                opts.smt = match args.next().as_deref() {
+                    Some("int") => Some(Logic::Int),
+                    Some("real") => Some(Logic::Real),
+                    l => return Err(format!("bad logic: {:?}", l)),
+                }
+            }
+            _ if a.starts_with("--") => return Err(format!("unknown option: {}", a)),
+            _ => opts.file = a,
+        }
+    }
+    if opts.file.is_empty() {
+        return Err("no input file".to_string());
+    }
+    if opts.deg.is_none() && (opts.acsl || opts.smt.is_some() || opts.cas.is_some()) {
+        return Err("--deg auto only prints text or --json".to_string());
+    }
+    Ok(opts)
+}
+
+fn main() {
+    let opts = match parse_args() {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let src = match std::fs::read_to_string(&opts.file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}: {}", opts.file, e);
+            std::process::exit(1);
+        }
+    };
+    let pe = match program().parse(&src) {
+        Ok((rest, e)) if rest.trim().is_empty() => e,
+        Ok((rest, _)) | Err(rest) => {
+            let at = src.len() - rest.len();
+            eprintln!("{}: parse error at byte {}", opts.file, at);
+            std::process::exit(1);
+        }
+    };
+    let r = Ring::new();
+    let e = convert_from_parseresult(&pe, &r);
+    let deg = match opts.deg {
+        Some(d) => d,
+        None => {
+            search(&e, &r, &opts);
+            return;
+        }
+    };
+    let rep = match analyze(&e, deg, opts.mode, &r) {
+        Ok(rep) => rep,
+        Err(err) => {
+            eprintln!("{}: {}", opts.file, err);
+            std::process::exit(1);
+        }
+    };
+    let invs: Vec<_> = rep.loops.iter().map(|l| l.polys.clone()).collect();
+    if opts.acsl {
+        match annotate(&src, &pe, &invs, &r) {
+            Ok(out) => print!("{}", out),
+            Err(err) => {
+                eprintln!("{}: {}", opts.file, err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(cas) = opts.cas {
+        let mut s = CasScript::new(cas, &r);
+        for l in &rep.loops {
+            s.polys(&format!("loop{}", l.index), &l.polys)
+                .pideal(&format!("head{}", l.index), &l.head);
+        }
+        s.equations("eqs", &rep.equations);
+        print!("{}", s.build());
+        return;
+    }
+    if let Some(logic) = opts.smt {
+        let vcs = match smt_vcs(&e, &invs, logic, &r) {
+            Ok(vcs) => vcs,
+            Err(err) => {
+                eprintln!("{}: {}", opts.file, err);
+                std::process::exit(1);
+            }
+        };
+        for vc in vcs {
+            println!("{}", vc.script);
+        }
+        return;
+    }
+    if opts.json {
+        println!("{}", rep.to_json());
+        return;
+    }
+    for l in &rep.loops {
+        println!("loop {}:", l.index);
+        for inv in &l.invariants {
+            println!("    {} = 0", inv.text);
+        }
+    }
+}
+
+// --deg auto. 次数を上げながら探して, 見つかった次数と一緒に出す
+fn search(e: &Expr, r: &RingRef, opts: &Opts) {
+    let rep = match search_degree(e, opts.mode, r, &Budget::default()) {
+        Ok(rep) => rep,
+        Err(err) => {
+            eprintln!("{}: {}", opts.file, err);
+            std::process::exit(1);
+        }
+    };
+    if opts.json {
+        println!("{}", rep.to_json());
+        return;
+    }
+    let stop = match rep.stop {
+        Stop::Saturated => "saturated",
+        Stop::MaxDeg => "degree limit",
+        Stop::MaxPars => "parameter limit",
+        Stop::Time => "time limit",
+    };
+    println!("searched up to degree {} ({})", rep.deg, stop);
+    for f in &rep.invs {
+        println!("    deg {}: {} = 0", f.deg, f.inv);
+    }
+}
@@ -10,16 +10,15 @@ use super::ring::*;
 Monomials
 */
 
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::hash::{Hash, Hasher};
-use std::rc::Rc;
 #[derive(PartialEq, Clone)]
 pub struct Mon<T: Coef> {
     // Var and Deg
     pub vars: HashMap<Var, usize>,
     pub coef: T,
-    pub r: Rc<RefCell<Ring>>,
+    pub r: RingRef,
 }
 
 impl<T: Coef> Hash for Mon<T> {
@@ -35,7 +34,7 @@ impl<T: Coef> std::fmt::Display for Mon<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut res: String;
         if self.is_cnst() {
-            res = format!("{:?}", self.coef);
+            res = self.coef.show(&self.r.borrow());
         } else {
             if self.coef == T::one() {
                 res = String::new();
@@ -44,7 +43,7 @@ impl<T: Coef> std::fmt::Display for Mon<T> {
             } else if self.coef == T::zero() {
                 panic!("zero term printed!")
             } else {
-                res = String::from(format!("{:?}", self.coef));
+                res = self.coef.show(&self.r.borrow());
             }
 
             {
@@ -107,7 +106,7 @@ impl<T: Coef> std::fmt::Debug for Mon<T> {
 
 impl<T: Coef> Mon<T> {
     // constantは, 変数
-    pub fn one(r: &Rc<RefCell<Ring>>) -> Mon<T> {
+    pub fn one(r: &RingRef) -> Mon<T> {
         Mon {
             vars: HashMap::new(),
             coef: T::one(),
@@ -116,7 +115,7 @@ impl<T: Coef> Mon<T> {
     }
 
     // 番兵用/ zero
-    pub fn zero(r: &Rc<RefCell<Ring>>) -> Mon<T> {
+    pub fn zero(r: &RingRef) -> Mon<T> {
         Mon {
             vars: HashMap::new(),
             coef: T::zero(),
@@ -129,8 +128,8 @@ impl<T: Coef> Mon<T> {
     }
 }
 
-impl<T: Coef> From<(Var, &Rc<RefCell<Ring>>)> for Mon<T> {
-    fn from(vr: (Var, &Rc<RefCell<Ring>>)) -> Self {
+impl<T: Coef> From<(Var, &RingRef)> for Mon<T> {
+    fn from(vr: (Var, &RingRef)) -> Self {
         let (v, r) = vr;
         let mut m = HashMap::new();
         m.insert(v, 1);
@@ -142,8 +141,8 @@ impl<T: Coef> From<(Var, &Rc<RefCell<Ring>>)> for Mon<T> {
     }
 }
 
-impl<T: Coef> From<(Vec<(Var, usize)>, &Rc<RefCell<Ring>>)> for Mon<T> {
-    fn from(vvr: (Vec<(Var, usize)>, &Rc<RefCell<Ring>>)) -> Self {
+impl<T: Coef> From<(Vec<(Var, usize)>, &RingRef)> for Mon<T> {
+    fn from(vvr: (Vec<(Var, usize)>, &RingRef)) -> Self {
         let (v, r) = vvr;
         Mon {
             vars: v.into_iter().collect(),
@@ -153,8 +152,8 @@ impl<T: Coef> From<(Vec<(Var, usize)>, &Rc<RefCell<Ring>>)> for Mon<T> {
     }
 }
 
-impl From<(Par, HashMap<Var, usize>, &Rc<RefCell<Ring>>)> for Mon<LinExp> {
-    fn from(pmr: (Par, HashMap<Var, usize>, &Rc<RefCell<Ring>>)) -> Self {
+impl From<(Par, HashMap<Var, usize>, &RingRef)> for Mon<LinExp> {
+    fn from(pmr: (Par, HashMap<Var, usize>, &RingRef)) -> Self {
         let (p, m, r) = pmr;
         Mon {
             vars: m,
@@ -164,8 +163,8 @@ impl From<(Par, HashMap<Var, usize>, &Rc<RefCell<Ring>>)> for Mon<LinExp> {
     }
 }
 
-impl<T: Coef> From<(HashMap<Var, usize>, &Rc<RefCell<Ring>>)> for Mon<T> {
-    fn from(mr: (HashMap<Var, usize>, &Rc<RefCell<Ring>>)) -> Self {
+impl<T: Coef> From<(HashMap<Var, usize>, &RingRef)> for Mon<T> {
+    fn from(mr: (HashMap<Var, usize>, &RingRef)) -> Self {
         let (m, r) = mr;
         Mon {
             vars: m,
@@ -175,8 +174,8 @@ impl<T: Coef> From<(HashMap<Var, usize>, &Rc<RefCell<Ring>>)> for Mon<T> {
     }
 }
 
-impl From<(Par, Vec<(Var, usize)>, &Rc<RefCell<Ring>>)> for Mon<LinExp> {
-    fn from(pmr: (Par, Vec<(Var, usize)>, &Rc<RefCell<Ring>>)) -> Self {
+impl From<(Par, Vec<(Var, usize)>, &RingRef)> for Mon<LinExp> {
+    fn from(pmr: (Par, Vec<(Var, usize)>, &RingRef)) -> Self {
         let (p, m, r) = pmr;
         Mon {
             vars: m.into_iter().collect(),
@@ -242,45 +241,241 @@ impl<T: Coef> std::cmp::Ord for Mon<T> {
         } else if rhs.coef == T::zero() {
             return std::cmp::Ordering::Greater;
         }
-        let lmd: usize = self.vars.iter().fold(0, |s, (_, d)| s + d);
-        let rmd: usize = rhs.vars.iter().fold(0, |s, (_, d)| s + d);
-        if lmd != rmd {
-            lmd.cmp(&rmd)
-        } else {
-            // ここがひどい
-
-            // 変数の一覧を取得
-            let mut m = self.vars.clone();
-            m.extend(rhs.vars.clone());
-            let mut diff: HashMap<Var, usize> = HashMap::new();
-            for k in m.keys() {
-                diff.insert(*k, 0);
+        self.cmp_vars(rhs)
+    }
+}
+
+impl<T: Coef> Mon<T> {
+    // pt にある変数を値で置き換える. 出てきた定数倍と, 残った変数を返す
+    pub fn eval_vars(&self, pt: &HashMap<Var, C>) -> (C, HashMap<Var, usize>) {
+        let mut c = C::one();
+        let mut rest = HashMap::new();
+        for (v, d) in &self.vars {
+            match pt.get(v) {
+                Some(x) => c *= num_traits::pow(*x, *d),
+                None => drop(rest.insert(*v, *d)),
             }
-            for (v, d) in self.vars.iter() {
-                diff.insert(*v, *d);
+        }
+        (c, rest)
+    }
+
+    // v で偏微分する. v を含まなければ 0 なので None
+    pub fn diff(&self, v: Var) -> Option<Mon<T>> {
+        let d = *self.vars.get(&v)?;
+        let mut m = self.clone();
+        if d == 1 {
+            m.vars.remove(&v);
+        } else {
+            m.vars.insert(v, d - 1);
+        }
+        Some(m * C::from_integer(d as i64))
+    }
+
+    // 係数を無視して, 変数部分だけを Grevlex で比較する
+    pub fn cmp_vars<S: Coef>(&self, rhs: &Mon<S>) -> std::cmp::Ordering {
+        cmp_exps(&self.vars, &rhs.vars)
+    }
+}
+
+fn cmp_exps(l: &HashMap<Var, usize>, r: &HashMap<Var, usize>) -> std::cmp::Ordering {
+    let lmd: usize = l.values().sum();
+    let rmd: usize = r.values().sum();
+    if lmd != rmd {
+        return lmd.cmp(&rmd);
+    }
+    // 次数が異なる変数のうち, 最も大きいものを探す
+    let mut last: Option<(Var, usize, usize)> = None;
+    for v in l.keys().chain(r.keys()) {
+        let ld = *l.get(v).unwrap_or(&0);
+        let rd = *r.get(v).unwrap_or(&0);
+        if ld != rd && last.map_or(true, |(lv, _, _)| lv < *v) {
+            last = Some((*v, ld, rd));
+        }
+    }
+    match last {
+        Some((_, ld, rd)) if ld > rd => std::cmp::Ordering::Less,
+        Some(_) => std::cmp::Ordering::Greater,
+        None => std::cmp::Ordering::Equal,
+    }
+}
+
+fn mul_exps(l: &HashMap<Var, usize>, r: &HashMap<Var, usize>) -> HashMap<Var, usize> {
+    let mut m = l.clone();
+    for (v, d) in r {
+        *m.entry(*v).or_insert(0) += d;
+    }
+    m
+}
+
+/*
+Sorted Monomial Lists
+*/
+// Poly, Temp の mons は Reverse で包んで昇順, つまり単項式の降順に並んでいる
+
+// 降順の単項式列同士をマージする. 同じ単項式は係数をまとめ, 0 になったものは消す
+pub fn merge_mons<T: Coef>(
+    a: Vec<Reverse<Mon<T>>>,
+    b: Vec<Reverse<Mon<T>>>,
+    r: &RingRef,
+) -> Vec<Reverse<Mon<T>>> {
+    let mut res = Vec::with_capacity(a.len() + b.len());
+    let mut a = a.into_iter().filter(|m| !m.0.coef.is_zero()).peekable();
+    let mut b = b.into_iter().filter(|m| !m.0.coef.is_zero()).peekable();
+    loop {
+        let o = match (a.peek(), b.peek()) {
+            (Some(x), Some(y)) => x.0.cmp_vars(&y.0),
+            (Some(_), None) => std::cmp::Ordering::Greater,
+            (None, Some(_)) => std::cmp::Ordering::Less,
+            (None, None) => break,
+        };
+        match o {
+            std::cmp::Ordering::Greater => res.extend(a.next()),
+            std::cmp::Ordering::Less => res.extend(b.next()),
+            std::cmp::Ordering::Equal => {
+                let (Reverse(mut m), Reverse(n)) = (a.next().unwrap(), b.next().unwrap());
+                m.coef += n.coef;
+                if !m.coef.is_zero() {
+                    res.push(Reverse(m));
+                }
             }
-            let mut diff_vec = vec![];
-            for (v, d) in diff {
-                match rhs.vars.get(&v) {
-                    Some(dv) => diff_vec.push((v, *dv as i64 - d as i64)),
-                    None => {
-                        diff_vec.push((v, -(d as i64)));
+        }
+    }
+    if res.is_empty() {
+        res.push(Reverse(Mon::zero(r)));
+    }
+    res
+}
+
+// ヒープに積むのは, a[i] * b[j] の変数部分
+#[derive(PartialEq, Eq)]
+struct HeapTerm {
+    vars: HashMap<Var, usize>,
+    i: usize,
+    j: usize,
+}
+
+impl std::cmp::PartialOrd for HeapTerm {
+    fn partial_cmp(&self, rhs: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(rhs))
+    }
+}
+
+impl std::cmp::Ord for HeapTerm {
+    fn cmp(&self, rhs: &Self) -> std::cmp::Ordering {
+        cmp_exps(&self.vars, &rhs.vars)
+    }
+}
+
+// Johnson のヒープ乗算. 積の単項式を大きい順に取り出すので, ソートし直さなくてよい
+pub fn mul_mons<T: Coef>(
+    a: &[Reverse<Mon<T>>],
+    b: &[Reverse<Mon<C>>],
+    r: &RingRef,
+) -> Vec<Reverse<Mon<T>>> {
+    let a: Vec<&Mon<T>> = a
+        .iter()
+        .map(|m| &m.0)
+        .filter(|m| !m.coef.is_zero())
+        .collect();
+    let b: Vec<&Mon<C>> = b
+        .iter()
+        .map(|m| &m.0)
+        .filter(|m| !m.coef.is_zero())
+        .collect();
+    let mut res = vec![];
+    if !a.is_empty() && !b.is_empty() {
+        let mut heap = BinaryHeap::with_capacity(a.len());
+        for (i, m) in a.iter().enumerate() {
+            heap.push(HeapTerm {
+                vars: mul_exps(&m.vars, &b[0].vars),
+                i,
+                j: 0,
+            });
+        }
+        let mut cur: Option<Mon<T>> = None;
+        while let Some(HeapTerm { vars, i, j }) = heap.pop() {
+            let c = a[i].coef.clone() * b[j].coef;
+            if cur
+                .as_ref()
+                .is_some_and(|m| cmp_exps(&m.vars, &vars).is_eq())
+            {
+                cur.as_mut().unwrap().coef += c;
+            } else {
+                if let Some(m) = cur.take() {
+                    if !m.coef.is_zero() {
+                        res.push(Reverse(m));
                     }
                 }
+                cur = Some(Mon {
+                    vars,
+                    coef: c,
+                    r: r.clone(),
+                });
             }
-            diff_vec.sort();
-            for i in (0..diff_vec.len()).rev() {
-                if diff_vec[i].1 < 0 {
-                    // self
-                    return std::cmp::Ordering::Less;
-                } else if diff_vec[i].1 > 0 {
-                    // rhs
-                    return std::cmp::Ordering::Greater;
-                }
+            if j + 1 < b.len() {
+                heap.push(HeapTerm {
+                    vars: mul_exps(&a[i].vars, &b[j + 1].vars),
+                    i,
+                    j: j + 1,
+                });
+            }
+        }
+        if let Some(m) = cur {
+            if !m.coef.is_zero() {
+                res.push(Reverse(m));
             }
-            return std::cmp::Ordering::Equal;
         }
     }
+    if res.is_empty() {
+        res.push(Reverse(Mon::zero(r)));
+    }
+    res
+}
+
+// v を含む単項式を v で割っても順序は変わらないので, 微分した列も降順のまま
+pub fn diff_mons<T: Coef>(mons: &[Reverse<Mon<T>>], v: Var, r: &RingRef) -> Vec<Reverse<Mon<T>>> {
+    let mut res: Vec<Reverse<Mon<T>>> = mons
+        .iter()
+        .filter(|m| !m.0.coef.is_zero())
+        .filter_map(|m| m.0.diff(v))
+        .map(Reverse)
+        .collect();
+    if res.is_empty() {
+        res.push(Reverse(Mon::zero(r)));
+    }
+    res
+}
+
+// 取り出した変数の冪と, 残りの単項式たち
+pub type MonGroup<T> = (Vec<(Var, usize)>, Vec<Reverse<Mon<T>>>);
+
+// 代入する変数の冪ごとに単項式を分け, その変数を取り除く.
+// 同じ単項式で割っただけなので, 各グループは降順のまま
+pub fn split_mons<T: Coef, F: Fn(&Var) -> bool>(
+    mons: &[Reverse<Mon<T>>],
+    is_subs: F,
+) -> Vec<MonGroup<T>> {
+    let mut groups: Vec<MonGroup<T>> = vec![];
+    let mut idx: HashMap<Vec<(Var, usize)>, usize> = HashMap::new();
+    for Reverse(m) in mons {
+        let mut m = m.clone();
+        let mut key: Vec<(Var, usize)> = m
+            .vars
+            .iter()
+            .filter(|(v, _)| is_subs(v))
+            .map(|(v, d)| (*v, *d))
+            .collect();
+        key.sort();
+        for (v, _) in &key {
+            m.vars.remove(v);
+        }
+        let i = *idx.entry(key.clone()).or_insert_with(|| {
+            groups.push((key, vec![]));
+            groups.len() - 1
+        });
+        groups[i].1.push(Reverse(m));
+    }
+    groups
 }
 
 #[test]
@@ -304,3 +499,34 @@ fn mon_ord_test() {
     dp.sort();
     println!("{:?}", dp);
 }
+
+#[test]
+fn sorted_mons_merge_mul() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+
+    let mons = |v: Vec<(Vec<(Var, usize)>, i64)>| -> Vec<Reverse<Mon<C>>> {
+        let mut res: Vec<Reverse<Mon<C>>> = v
+            .into_iter()
+            .map(|(m, c)| Reverse(Mon::from((m, &r)) * C::from_integer(c)))
+            .collect();
+        res.sort();
+        res
+    };
+    // (x + y) + (x - y) = 2x
+    let a = mons(vec![(vec![(x, 1)], 1), (vec![(y, 1)], 1)]);
+    let b = mons(vec![(vec![(x, 1)], 1), (vec![(y, 1)], -1)]);
+    assert!(merge_mons(a.clone(), b.clone(), &r) == mons(vec![(vec![(x, 1)], 2)]));
+    // (x + y) - (x + y) = 0
+    let c = mons(vec![(vec![(x, 1)], -1), (vec![(y, 1)], -1)]);
+    assert!(merge_mons(a.clone(), c, &r) == vec![Reverse(Mon::zero(&r))]);
+    // (x + y)(x - y) = x^2 - y^2
+    let p = mul_mons(&a, &b, &r);
+    assert!(p == mons(vec![(vec![(x, 2)], 1), (vec![(y, 2)], -1)]));
+    // 結果はソート済み
+    let mut q = mul_mons(&p, &a, &r);
+    let sorted = q.clone();
+    q.sort();
+    assert!(q == sorted);
+}
@@ -110,6 +110,36 @@ pub fn space1<'a>() -> impl Parser<'a, Vec<char>> {
 pub fn space0<'a>() -> impl Parser<'a, Vec<char>> {
     zero_or_more(whitespace_char())
 }
+// 行末までのコメント. 注釈 (//@ ...) もここでは読み飛ばす
+pub fn comment<'a>() -> impl Parser<'a, ()> {
+    right(
+        match_literal("//"),
+        zero_or_more(pred(any_char, |c| *c != '\n')),
+    )
+    .map(|_| ())
+}
+// /* ... */ のコメント. ACSL の注釈 (/*@ ... */) も読み飛ばす
+pub fn block_comment<'a>() -> impl Parser<'a, ()> {
+    move |input: &'a str| match input.strip_prefix("/*") {
+        Some(rest) => match rest.find("*/") {
+            Some(i) => Ok((&rest[i + 2..], ())),
+            None => Err(input),
+        },
+        None => Err(input),
+    }
+}
+// 入力を消費せずに残りの長さを返す. ソースの先頭からの位置は src.len() - これ
+pub fn rest_len<'a>() -> impl Parser<'a, usize> {
+    move |input: &'a str| Ok((input, input.len()))
+}
+// 空白とコメント
+pub fn skip0<'a>() -> impl Parser<'a, ()> {
+    zero_or_more(either(
+        whitespace_char().map(|_| ()),
+        either(comment(), block_comment()),
+    ))
+    .map(|_| ())
+}
 
 // Parser Combinator
 pub fn map<'a, P, F, A, B>(parser: P, map_fn: F) -> impl Parser<'a, B>
@@ -248,5 +278,5 @@ pub fn whitespace_wrap<'a, P, A>(parser: P) -> impl Parser<'a, A>
 where
     P: Parser<'a, A>,
 {
-    right(space0(), left(parser, space0()))
+    right(skip0(), left(parser, skip0()))
 }
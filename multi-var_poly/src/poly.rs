@@ -1,14 +1,13 @@
 use super::coef::*;
 use super::mon::*;
 use super::ring::*;
-use std::cell::RefCell;
 use std::cmp::Reverse;
-use std::rc::Rc;
+use std::collections::HashMap;
 
 #[derive(PartialEq, Eq, Clone)]
 pub struct Poly {
     pub mons: Vec<Reverse<Mon<C>>>,
-    pub r: Rc<RefCell<Ring>>,
+    pub r: RingRef,
 }
 
 // display, debug
@@ -29,10 +28,10 @@ impl std::fmt::Debug for Poly {
 
 // constructors
 impl Poly {
-    pub fn one(r: &Rc<RefCell<Ring>>) -> Poly {
+    pub fn one(r: &RingRef) -> Poly {
         Poly::from((vec![Mon::<C>::one(&r)], r))
     }
-    pub fn zero(r: &Rc<RefCell<Ring>>) -> Poly {
+    pub fn zero(r: &RingRef) -> Poly {
         Poly::from((vec![Mon::<C>::zero(&r)], r))
     }
 }
@@ -43,8 +42,8 @@ fn test_zero() {
     println!("{:?}", Poly::zero(&r));
 }
 
-impl From<(Vec<Mon<C>>, &Rc<RefCell<Ring>>)> for Poly {
-    fn from(a: (Vec<Mon<C>>, &Rc<RefCell<Ring>>)) -> Self {
+impl From<(Vec<Mon<C>>, &RingRef)> for Poly {
+    fn from(a: (Vec<Mon<C>>, &RingRef)) -> Self {
         let mut mons = vec![];
         for m in a.0 {
             mons.push(Reverse(m));
@@ -58,8 +57,8 @@ impl From<(Vec<Mon<C>>, &Rc<RefCell<Ring>>)> for Poly {
     }
 }
 
-impl From<(Var, &Rc<RefCell<Ring>>)> for Poly {
-    fn from(vr: (Var, &Rc<RefCell<Ring>>)) -> Self {
+impl From<(Var, &RingRef)> for Poly {
+    fn from(vr: (Var, &RingRef)) -> Self {
         let (v, r) = vr;
         Poly {
             mons: vec![Reverse(Mon::from((v, r)))],
@@ -68,8 +67,8 @@ impl From<(Var, &Rc<RefCell<Ring>>)> for Poly {
     }
 }
 
-impl From<(C, &Rc<RefCell<Ring>>)> for Poly {
-    fn from(cr: (C, &Rc<RefCell<Ring>>)) -> Self {
+impl From<(C, &RingRef)> for Poly {
+    fn from(cr: (C, &RingRef)) -> Self {
         let (c, r) = cr;
         Poly {
             mons: vec![Reverse(Mon::one(r) * c)],
@@ -85,31 +84,24 @@ impl Poly {
         self.mons[0].0 == Mon::zero(&self.r)
     }
     fn sort_sumup(&mut self) {
-        // dummy monomial
-        let dm = Reverse(Mon::<C>::zero(&self.r));
-        // 0を追加して, 最後にまとめて消す
-        self.mons.sort();
-        for i in 1..self.mons.len() {
-            if !(self.mons[i - 1] > self.mons[i]) && !(self.mons[i - 1] < self.mons[i]) {
-                let c = self.mons[i].0.coef;
-                self.mons[i - 1].0.coef += c;
-                self.mons[i] = dm.clone();
-                if self.mons[i - 1].0.coef == C::zero() {
-                    self.mons[i - 1] = dm.clone();
-                }
+        // 同じ単項式を隣り合わせに並べてから, 係数をまとめる
+        let mut mons = std::mem::take(&mut self.mons);
+        mons.sort_by(|m1, m2| m2.0.cmp_vars(&m1.0));
+        let mut res: Vec<Reverse<Mon<C>>> = vec![];
+        for Reverse(m) in mons {
+            if m.coef.is_zero() {
+                continue;
             }
-        }
-        self.mons.sort();
-        while let Some(m) = self.mons.pop() {
-            if m != dm {
-                self.mons.push(m);
-                break;
+            match res.last_mut() {
+                Some(Reverse(l)) if l.cmp_vars(&m).is_eq() => l.coef += m.coef,
+                _ => res.push(Reverse(m)),
             }
         }
-
-        if self.mons.len() == 0 {
-            self.mons.push(dm);
+        res.retain(|m| !m.0.coef.is_zero());
+        if res.is_empty() {
+            res.push(Reverse(Mon::zero(&self.r)));
         }
+        self.mons = res;
     }
     pub fn tdeg(&self) -> usize {
         let m = self.mons.iter().min().expect("Poly T-degree Panic");
@@ -129,7 +121,107 @@ impl Poly {
         res.sort_sumup();
         res
     }
+
+    // 環が違えば演算子は panic するので, エラーで受け取りたいときはこちら
+    pub fn checked_add(self, rhs: Poly) -> Result<Poly, RingMismatch> {
+        self.r.check(&rhs.r)?;
+        Ok(self + rhs)
+    }
+    pub fn checked_sub(self, rhs: Poly) -> Result<Poly, RingMismatch> {
+        self.r.check(&rhs.r)?;
+        Ok(self - rhs)
+    }
+    pub fn checked_mul(self, rhs: Poly) -> Result<Poly, RingMismatch> {
+        self.r.check(&rhs.r)?;
+        Ok(self * rhs)
+    }
+
+    // 全ての変数に値を与えて評価する
+    pub fn eval(&self, pt: &HashMap<Var, C>) -> C {
+        let mut res = C::zero();
+        for Reverse(m) in &self.mons {
+            let (c, rest) = m.eval_vars(pt);
+            if let Some(v) = rest.keys().next() {
+                panic!("no value for {}", self.r.borrow().vars[v]);
+            }
+            res += m.coef * c;
+        }
+        res
+    }
+
+    // pt にある変数だけを評価して, 残りの変数の多項式にする
+    pub fn partial_eval(&self, pt: &HashMap<Var, C>) -> Poly {
+        let mut mons = vec![];
+        for Reverse(m) in &self.mons {
+            let (c, rest) = m.eval_vars(pt);
+            mons.push(Mon::from((rest, &self.r)) * (m.coef * c));
+        }
+        Poly::from((mons, &self.r))
+    }
+
+    // map にある変数を同時に置き換える. 置き換えた結果にまた代入はしない
+    pub fn subs_many(&self, map: &HashMap<Var, Poly>) -> Poly {
+        let mut cache = HashMap::new();
+        let mut res = Poly::zero(&self.r);
+        for (key, mons) in split_mons(&self.mons, |v| map.contains_key(v)) {
+            let f = subs_factor(&key, map, &mut cache, &self.r);
+            res += Poly {
+                mons,
+                r: self.r.clone(),
+            } * f;
+        }
+        res
+    }
+
+    pub fn diff(&self, v: Var) -> Poly {
+        Poly {
+            mons: diff_mons(&self.mons, v, &self.r),
+            r: self.r.clone(),
+        }
+    }
+    // vs の順に偏微分を並べる
+    pub fn gradient(&self, vs: &[Var]) -> Vec<Poly> {
+        vs.iter().map(|v| self.diff(*v)).collect()
+    }
+    // i 行 j 列が ps[i] の vs[j] での偏微分
+    pub fn jacobian(ps: &[Poly], vs: &[Var]) -> Vec<Vec<Poly>> {
+        ps.iter().map(|p| p.gradient(vs)).collect()
+    }
+
+    // 変数を付け替えて別の環に移す. 順序が変わるのでソートし直す
+    pub fn map_vars(&self, map: &HashMap<Var, Var>, r: &RingRef) -> Poly {
+        let mut mons = vec![];
+        for Reverse(m) in &self.mons {
+            let vars: HashMap<Var, usize> = m.vars.iter().map(|(v, d)| (map[v], *d)).collect();
+            let mut new_m: Mon<C> = Mon::from((vars, r));
+            new_m.coef = m.coef;
+            mons.push(new_m);
+        }
+        Poly::from((mons, r))
+    }
+
+    // 変数名をたよりに r へ移す
+    pub fn to_ring(&self, r: &RingRef) -> Result<Poly, RingMismatch> {
+        let map = Ring::var_map(&self.r, r)?;
+        Ok(self.map_vars(&map, r))
+    }
+}
+
+// 代入先の冪の積. 冪は cache に溜めて使い回す
+pub fn subs_factor(
+    key: &[(Var, usize)],
+    map: &HashMap<Var, Poly>,
+    cache: &mut HashMap<(Var, usize), Poly>,
+    r: &RingRef,
+) -> Poly {
+    let mut f = Poly::one(r);
+    for (v, d) in key {
+        let p = cache.entry((*v, *d)).or_insert_with(|| map[v].pow(*d));
+        f *= p.clone();
+    }
+    f
 }
+
 #[test]
 fn check_poly_pow() {
     // 0 -> x, 1 -> y, 2 -> z
@@ -161,56 +253,48 @@ impl std::ops::Add<Poly> for Poly {
     type Output = Poly;
 
     fn add(mut self, rhs: Poly) -> Self::Output {
-        self.mons.extend(rhs.mons);
-        self.sort_sumup();
+        self.r.assert_same(&rhs.r);
+        self.mons = merge_mons(self.mons, rhs.mons, &self.r);
         self
     }
 }
 
 impl std::ops::AddAssign<Poly> for Poly {
     fn add_assign(&mut self, rhs: Poly) {
-        *self = self.clone() + rhs;
+        self.r.assert_same(&rhs.r);
+        let lhs = std::mem::take(&mut self.mons);
+        self.mons = merge_mons(lhs, rhs.mons, &self.r);
     }
 }
 
 impl std::ops::Sub<Poly> for Poly {
     type Output = Poly;
 
-    fn sub(mut self, mut rhs: Poly) -> Self::Output {
-        // TODO: 激オソなので, 改善しよう
-        rhs = -rhs;
-        self.mons.extend(rhs.mons);
-        self.sort_sumup();
-        self
+    fn sub(self, rhs: Poly) -> Self::Output {
+        self + -rhs
     }
 }
 
 impl std::ops::SubAssign<Poly> for Poly {
     fn sub_assign(&mut self, rhs: Poly) {
-        *self = self.clone() - rhs;
+        *self += -rhs;
     }
 }
 
-// TODO: O(N^2)ですが
 impl std::ops::Mul<Poly> for Poly {
     type Output = Poly;
 
     fn mul(mut self, rhs: Poly) -> Self::Output {
-        let mut tmp = vec![];
-        for m1 in &self.mons {
-            for m2 in &rhs.mons {
-                tmp.push(Reverse(m1.0.clone() * m2.0.clone()));
-            }
-        }
-        self.mons = tmp;
-        self.sort_sumup();
+        self.r.assert_same(&rhs.r);
+        self.mons = mul_mons(&self.mons, &rhs.mons, &self.r);
         self
     }
 }
 
 impl std::ops::MulAssign<Poly> for Poly {
     fn mul_assign(&mut self, rhs: Poly) {
-        *self = self.clone() * rhs;
+        self.r.assert_same(&rhs.r);
+        self.mons = mul_mons(&self.mons, &rhs.mons, &self.r);
     }
 }
 
@@ -236,3 +320,163 @@ fn check_poly_addition() {
     assert!(a.tdeg() == 2);
     println!("{:?}", a);
 }
+
+#[test]
+fn check_poly_ops_sorted() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let px = Poly::from((x, &r));
+    let py = Poly::from((y, &r));
+    let one = Poly::one(&r);
+
+    // (x + y + 1)^3 を掛け算とソートの両方で作って比べる
+    let p = px.clone() + py.clone() + one.clone();
+    let cube = p.clone() * p.clone() * p.clone();
+    let mons: Vec<Mon<C>> = cube.mons.iter().map(|m| m.0.clone()).collect();
+    let resorted = Poly::from((mons, &r));
+    assert!(cube == resorted);
+    assert!(cube == p.pow(3));
+    assert!(cube.tdeg() == 3);
+    assert!(cube.mons.len() == 10);
+
+    // (x + y)(x - y) = x^2 - y^2
+    let d = (px.clone() + py.clone()) * (px.clone() - py.clone());
+    assert!(d == px.pow(2) - py.pow(2));
+    // p - p = 0
+    assert!((p.clone() - p.clone()).is_zero());
+    assert!((p.clone() * Poly::zero(&r)).is_zero());
+    let mut q = p.clone();
+    q -= p;
+    assert!(q == Poly::zero(&r));
+}
+
+#[test]
+fn check_ring_mismatch() {
+    let r1 = Ring::new();
+    let x = r1.borrow_mut().vextend("x".to_string());
+    let y = r1.borrow_mut().vextend("y".to_string());
+    let r2 = Ring::new();
+    let y2 = r2.borrow_mut().vextend("y".to_string());
+    let z = r2.borrow_mut().vextend("z".to_string());
+
+    let p = Poly::from((x, &r1)) + Poly::from((y, &r1));
+    let q = Poly::from((y2, &r2)) * Poly::from((z, &r2));
+    assert!(p.clone().checked_add(q.clone()).is_err());
+    assert!(p.clone().checked_mul(q.clone()).is_err());
+    let e = p.clone().checked_sub(q.clone()).unwrap_err();
+    assert!(e.left == vec!["x", "y"] && e.right == vec!["y", "z"]);
+
+    // 共通の環にまとめれば足せる
+    let m = Ring::merge(&r1, &r2);
+    let s = p.map_vars(&m.left, &m.ring) + q.map_vars(&m.right, &m.ring);
+    let x = m.ring.borrow().var_by_name("x").unwrap();
+    let y = m.ring.borrow().var_by_name("y").unwrap();
+    let z = m.ring.borrow().var_by_name("z").unwrap();
+    let v = |v: Var| Poly::from((v, &m.ring));
+    assert!(s == v(x) + v(y) + v(y) * v(z));
+    assert!(q.to_ring(&m.ring).unwrap() == v(y) * v(z));
+    assert!(s.to_ring(&r1).is_err());
+}
+
+#[test]
+#[should_panic(expected = "different rings")]
+fn check_ring_mismatch_panics() {
+    let r1 = Ring::new();
+    let x = r1.borrow_mut().vextend("x".to_string());
+    let r2 = Ring::new();
+    r2.borrow_mut().vextend("y".to_string());
+    let z = r2.borrow_mut().vextend("z".to_string());
+    let _ = Poly::from((x, &r1)) + Poly::from((z, &r2));
+}
+
+#[test]
+fn check_poly_eval() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+    // p = x^2 y - 3y + 1/2
+    let p = px.pow(2) * py.clone() - py.clone() * Poly::from((C::new(3, 1), &r))
+        + Poly::from((C::new(1, 2), &r));
+    let pt: HashMap<Var, C> = vec![(x, C::new(2, 1)), (y, C::new(-1, 3))]
+        .into_iter()
+        .collect();
+    // 4 * (-1/3) + 1 + 1/2
+    assert!(p.eval(&pt) == C::new(1, 6));
+    assert!(Poly::zero(&r).eval(&pt) == C::zero());
+
+    // x = 2 だけ入れると 4y - 3y + 1/2 = y + 1/2
+    let px2: HashMap<Var, C> = vec![(x, C::new(2, 1))].into_iter().collect();
+    let q = p.partial_eval(&px2);
+    assert!(q == py.clone() + Poly::from((C::new(1, 2), &r)));
+    assert!(q.eval(&pt) == p.eval(&pt));
+    // 全部入れると定数
+    assert!(p.partial_eval(&pt) == Poly::from((C::new(1, 6), &r)));
+    // x = 0 で消える項は消える
+    let px0: HashMap<Var, C> = vec![(x, C::zero())].into_iter().collect();
+    assert!(
+        p.partial_eval(&px0)
+            == Poly::from((C::new(1, 2), &r)) - py * Poly::from((C::new(3, 1), &r))
+    );
+}
+
+#[test]
+fn check_poly_subs_many() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+    let one = Poly::one(&r);
+    // p = x^2 y + x - 1
+    let p = px.pow(2) * py.clone() + px.clone() - one.clone();
+    // (x, y) = (y, x) は同時に入れ替わる
+    let swap: HashMap<Var, Poly> = vec![(x, py.clone()), (y, px.clone())].into_iter().collect();
+    let q = p.subs_many(&swap);
+    println!("{:?} -> {:?}", p, q);
+    assert!(q == py.pow(2) * px.clone() + py.clone() - one.clone());
+    assert!(q.subs_many(&swap) == p);
+    // 一変数だけでもよい. x = y + 1
+    let sx: HashMap<Var, Poly> = vec![(x, py.clone() + one.clone())].into_iter().collect();
+    let q = p.subs_many(&sx);
+    assert!(q == (py.clone() + one.clone()).pow(2) * py.clone() + py.clone());
+    assert!(Poly::zero(&r).subs_many(&swap) == Poly::zero(&r));
+}
+
+#[test]
+fn check_poly_diff() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+    let c = |n| Poly::from((C::new(n, 1), &r));
+    // p = x^3 y + 2xy^2 - y + 5
+    let p = px.pow(3) * py.clone() + c(2) * px.clone() * py.pow(2) - py.clone() + c(5);
+    let dx = c(3) * px.pow(2) * py.clone() + c(2) * py.pow(2);
+    let dy = px.pow(3) + c(4) * px.clone() * py.clone() - c(1);
+    println!("d/dx {:?} = {:?}", p, p.diff(x));
+    assert!(p.diff(x) == dx);
+    assert!(p.diff(y) == dy);
+    assert!(c(5).diff(x) == Poly::zero(&r));
+    assert!(Poly::zero(&r).diff(x) == Poly::zero(&r));
+    let vs = r.borrow().var_list();
+    assert!(p.gradient(&vs) == vec![dx.clone(), dy.clone()]);
+    // J(p, xy) = [[dx, dy], [y, x]]
+    let j = Poly::jacobian(&[p, px.clone() * py.clone()], &vs);
+    assert!(j == vec![vec![dx, dy], vec![py.clone(), px.clone()]]);
+    // 円 x^2 + y^2 - 1 の勾配は原点でしか消えないので, 特異点はない
+    let g = (px.pow(2) + py.pow(2) - c(1)).gradient(&vs);
+    let o: HashMap<Var, C> = vec![(x, C::zero()), (y, C::zero())].into_iter().collect();
+    assert!(g.iter().all(|d| d.eval(&o) == C::zero()));
+}
+
+#[test]
+#[should_panic(expected = "no value for y")]
+fn check_poly_eval_missing_var() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y = r.borrow_mut().vextend("y".to_string());
+    let p = Poly::from((x, &r)) * Poly::from((y, &r));
+    let pt: HashMap<Var, C> = vec![(x, C::one())].into_iter().collect();
+    p.eval(&pt);
+}
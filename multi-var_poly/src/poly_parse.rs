@@ -3,8 +3,6 @@ use super::mon::*;
 use super::p_comb::*;
 use super::poly::*;
 use super::ring::*;
-use std::cell::RefCell;
-use std::rc::Rc;
 // poly := term ('+' term | '-' term)*;
 // term := factor ('*' factor)*;
 // factor := unary ('^' number)*;
@@ -278,7 +276,7 @@ fn pow_calc(p: &P) -> usize {
     }
 }
 
-pub fn create_poly(p: &P, r: &Rc<RefCell<Ring>>) -> Poly {
+pub fn create_poly(p: &P, r: &RingRef) -> Poly {
     match p {
         P::Add { exp1, exp2 } => create_poly(exp1, r) + create_poly(exp2, r),
         P::Mul { exp1, exp2 } => create_poly(exp1, r) * create_poly(exp2, r),
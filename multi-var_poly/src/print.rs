@@ -0,0 +1,401 @@
+use super::coef::*;
+use super::constraints::*;
+use super::ideal::*;
+use super::poly::*;
+use super::ring::*;
+use super::temp::*;
+use num_traits::Signed;
+
+/*
+Printer
+*/
+// 多項式, テンプレート, 1 次式, イデアル, 連立方程式を同じ規則で文字列にする.
+// 単項式は多項式の並び (Grevlex の降順), 単項式の中の変数は Var の番号順に並べる
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+    // 2*x^2*y - 3/2*z
+    Plain,
+    // 2*x*x*y - (3.0/2.0)*z
+    C,
+    // 2 x^{2} y - \frac{3}{2} z
+    Latex,
+    // (+ (* 2 x x y) (* (- (/ 3 2)) z))
+    Smt,
+    // Plain と同じだが名前は英数字だけ. Singular, Macaulay2, Sage で読める
+    Cas,
+}
+
+pub struct Printer {
+    pub syn: Syntax,
+    r: RingRef,
+}
+
+// 係数 c と, 括弧に入れる係数 (テンプレートの 1 次式), 変数と次数の積
+struct Term {
+    c: C,
+    lin: Option<String>,
+    factors: Vec<(String, usize)>,
+}
+
+impl Printer {
+    pub fn new(syn: Syntax, r: &RingRef) -> Printer {
+        Printer { syn, r: r.clone() }
+    }
+
+    // 名前をそれぞれの文法で使える形にする. a[3] は a_3 (LaTeX では a_{3}, CAS では a3)
+    fn ident(&self, name: &str) -> String {
+        match self.syn {
+            Syntax::Plain => name.to_string(),
+            Syntax::C | Syntax::Smt => {
+                let mut s: String = name
+                    .chars()
+                    .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                    .collect();
+                while s.ends_with('_') {
+                    s.pop();
+                }
+                if s.starts_with(|c: char| c.is_ascii_digit()) {
+                    s.insert(0, '_');
+                }
+                s
+            }
+            // Macaulay2 では _ が演算子なので使わない
+            Syntax::Cas => {
+                let mut s: String = name.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+                if !s.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                    s.insert(0, 'v');
+                }
+                s
+            }
+            Syntax::Latex => {
+                let name = name.replace(']', "");
+                let i = name
+                    .find(|c: char| c == '_' || c == '[' || c.is_ascii_digit())
+                    .unwrap_or(name.len());
+                let (base, sub) = name.split_at(i);
+                let sub = sub.trim_start_matches(['_', '[']);
+                let base = if base.chars().count() > 1 {
+                    format!("\\mathit{{{}}}", base)
+                } else {
+                    base.to_string()
+                };
+                if sub.is_empty() {
+                    base
+                } else {
+                    format!("{}_{{{}}}", base, sub)
+                }
+            }
+        }
+    }
+
+    pub fn var(&self, v: Var) -> String {
+        let name = match self.r.borrow().vars.get(&v) {
+            Some(s) => s.clone(),
+            // 環にない補助変数
+            None => format!("{:?}", v),
+        };
+        self.ident(&name)
+    }
+
+    // a[0] は CAS では a0 になり, 変数 a0 と区別できなくなる.
+    // 変数の名前はそのままにして, ぶつかるパラメーターの方に p を足す
+    pub fn par(&self, p: Par) -> String {
+        let r = self.r.borrow();
+        let mut s = self.ident(&r.par_name(p));
+        while r.vars.values().any(|v| self.ident(v) == s) {
+            s.push('p');
+        }
+        s
+    }
+
+    // 符号なしの有理数
+    fn rat(&self, c: C) -> String {
+        let (n, d) = (c.numer().abs(), *c.denom());
+        match self.syn {
+            _ if d == 1 => n.to_string(),
+            Syntax::Plain | Syntax::Cas => format!("{}/{}", n, d),
+            Syntax::C => format!("({}.0/{}.0)", n, d),
+            Syntax::Latex => format!("\\frac{{{}}}{{{}}}", n, d),
+            Syntax::Smt => format!("(/ {} {})", n, d),
+        }
+    }
+
+    fn smt_const(&self, c: C) -> String {
+        if c < C::zero() {
+            format!("(- {})", self.rat(c))
+        } else {
+            self.rat(c)
+        }
+    }
+
+    // 符号を除いた項. SMT では符号も含める
+    fn term(&self, t: &Term) -> String {
+        let mut parts = vec![];
+        let unit = t.c.abs() == C::one() && (t.lin.is_some() || !t.factors.is_empty());
+        match self.syn {
+            Syntax::Smt => {
+                if !unit || t.c < C::zero() {
+                    parts.push(self.smt_const(t.c));
+                }
+                parts.extend(t.lin.clone());
+                for (f, d) in &t.factors {
+                    parts.extend(std::iter::repeat(f.clone()).take(*d));
+                }
+                if parts.len() == 1 {
+                    parts.pop().unwrap()
+                } else {
+                    format!("(* {})", parts.join(" "))
+                }
+            }
+            _ => {
+                if !unit {
+                    parts.push(self.rat(t.c));
+                }
+                if let Some(l) = &t.lin {
+                    parts.push(match self.syn {
+                        Syntax::Latex => format!("\\left({}\\right)", l),
+                        _ => format!("({})", l),
+                    });
+                }
+                for (f, d) in &t.factors {
+                    match (self.syn, d) {
+                        (_, 1) => parts.push(f.clone()),
+                        (Syntax::Plain | Syntax::Cas, _) => parts.push(format!("{}^{}", f, d)),
+                        (Syntax::Latex, _) => parts.push(format!("{}^{{{}}}", f, d)),
+                        _ => parts.extend(std::iter::repeat(f.clone()).take(*d)),
+                    }
+                }
+                parts.join(if self.syn == Syntax::Latex { " " } else { "*" })
+            }
+        }
+    }
+
+    fn sum(&self, ts: &[Term]) -> String {
+        let ts: Vec<&Term> = ts.iter().filter(|t| !t.c.is_zero()).collect();
+        if ts.is_empty() {
+            return "0".to_string();
+        }
+        if self.syn == Syntax::Smt {
+            let ss: Vec<String> = ts.iter().map(|t| self.term(t)).collect();
+            return if ss.len() == 1 {
+                ss[0].clone()
+            } else {
+                format!("(+ {})", ss.join(" "))
+            };
+        }
+        let mut res = String::new();
+        for (i, t) in ts.iter().enumerate() {
+            let neg = t.c < C::zero();
+            res += match (i, neg) {
+                (0, true) => "-",
+                (0, false) => "",
+                (_, true) => " - ",
+                (_, false) => " + ",
+            };
+            res += &self.term(t);
+        }
+        res
+    }
+
+    fn factors(&self, vars: &std::collections::HashMap<Var, usize>) -> Vec<(String, usize)> {
+        let mut vs: Vec<(&Var, &usize)> = vars.iter().collect();
+        vs.sort();
+        vs.into_iter().map(|(v, d)| (self.var(*v), *d)).collect()
+    }
+
+    fn lin_terms(&self, l: &LinExp) -> Vec<Term> {
+        l.terms
+            .iter()
+            .map(|pt| Term {
+                c: pt.coef,
+                lin: None,
+                factors: pt.par.map(|p| (self.par(p), 1)).into_iter().collect(),
+            })
+            .collect()
+    }
+
+    pub fn poly(&self, p: &Poly) -> String {
+        let ts: Vec<Term> = p
+            .mons
+            .iter()
+            .map(|m| Term {
+                c: m.0.coef,
+                lin: None,
+                factors: self.factors(&m.0.vars),
+            })
+            .collect();
+        self.sum(&ts)
+    }
+
+    pub fn lin(&self, l: &LinExp) -> String {
+        self.sum(&self.lin_terms(l))
+    }
+
+    pub fn temp(&self, t: &Temp) -> String {
+        let ts: Vec<Term> = t
+            .mons
+            .iter()
+            .map(|m| {
+                let fs = self.factors(&m.0.vars);
+                let nonzero: Vec<&ParTerm> =
+                    m.0.coef
+                        .terms
+                        .iter()
+                        .filter(|pt| !pt.coef.is_zero())
+                        .collect();
+                match nonzero.as_slice() {
+                    // 1 項だけなら係数をそのまま前に出す
+                    [pt] => {
+                        let mut factors: Vec<(String, usize)> =
+                            pt.par.map(|p| (self.par(p), 1)).into_iter().collect();
+                        factors.extend(fs);
+                        Term {
+                            c: pt.coef,
+                            lin: None,
+                            factors,
+                        }
+                    }
+                    _ => Term {
+                        c: C::one(),
+                        lin: Some(self.lin(&m.0.coef)),
+                        factors: fs,
+                    },
+                }
+            })
+            .collect();
+        self.sum(&ts)
+    }
+
+    // Plain と LaTeX ではイデアル, C と SMT では生成元がみな 0 という条件
+    fn gens(&self, mut gs: Vec<String>) -> String {
+        gs.sort();
+        match self.syn {
+            Syntax::Plain | Syntax::Cas => format!("<{}>", gs.join(", ")),
+            Syntax::Latex => format!("\\langle {} \\rangle", gs.join(", ")),
+            Syntax::C => {
+                let eqs: Vec<String> = gs.iter().map(|g| format!("{} == 0", g)).collect();
+                if eqs.is_empty() {
+                    "1".to_string()
+                } else {
+                    eqs.join(" && ")
+                }
+            }
+            Syntax::Smt => {
+                let eqs: Vec<String> = gs.iter().map(|g| format!("(= {} 0)", g)).collect();
+                match eqs.len() {
+                    0 => "true".to_string(),
+                    1 => eqs[0].clone(),
+                    _ => format!("(and {})", eqs.join(" ")),
+                }
+            }
+        }
+    }
+
+    pub fn pideal(&self, i: &PIdeal) -> String {
+        self.gens(i.gens.iter().map(|t| self.temp(t)).collect())
+    }
+
+    pub fn ideal(&self, i: &Ideal) -> String {
+        self.gens(i.gens.iter().map(|p| self.poly(p)).collect())
+    }
+
+    // 式 Σ a p = c を一行に一つ
+    pub fn equation(&self, l: &LinExp, c: C) -> String {
+        let lhs = self.lin(l);
+        match self.syn {
+            Syntax::Plain | Syntax::Latex | Syntax::Cas => {
+                format!("{} = {}", lhs, self.sum(&[cnst(c)]))
+            }
+            Syntax::C => format!("{} == {}", lhs, self.sum(&[cnst(c)])),
+            Syntax::Smt => format!("(= {} {})", lhs, self.smt_const(c)),
+        }
+    }
+
+    pub fn equations(&self, le: &LinearEquations) -> String {
+        let mut rows: Vec<String> = le
+            .rows()
+            .iter()
+            .map(|(l, c)| self.equation(l, *c))
+            .collect();
+        rows.sort();
+        rows.join("\n")
+    }
+}
+
+fn cnst(c: C) -> Term {
+    Term {
+        c,
+        lin: None,
+        factors: vec![],
+    }
+}
+
+impl std::fmt::Display for Poly {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", Printer::new(Syntax::Plain, &self.r).poly(self))
+    }
+}
+
+impl std::fmt::Display for Temp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", Printer::new(Syntax::Plain, &self.r).temp(self))
+    }
+}
+
+impl std::fmt::Display for Ideal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", Printer::new(Syntax::Plain, &self.r).ideal(self))
+    }
+}
+
+#[test]
+fn check_printer() {
+    let r = Ring::new();
+    let x = r.borrow_mut().vextend("x".to_string());
+    let y1 = r.borrow_mut().vextend("y1".to_string());
+    let (px, py) = (Poly::from((x, &r)), Poly::from((y1, &r)));
+    let c = |n, d| Poly::from((C::new(n, d), &r));
+    // 2 x^2 y1 - 3/2 y1 + 1
+    let p = c(2, 1) * px.pow(2) * py.clone() - c(3, 2) * py.clone() + Poly::one(&r);
+    let show = |s| Printer::new(s, &r).poly(&p);
+    println!(
+        "{}\n{}\n{}\n{}",
+        show(Syntax::Plain),
+        show(Syntax::C),
+        show(Syntax::Latex),
+        show(Syntax::Smt)
+    );
+    assert!(show(Syntax::Plain) == "2*x^2*y1 - 3/2*y1 + 1");
+    assert!(format!("{}", p) == show(Syntax::Plain));
+    assert!(show(Syntax::C) == "2*x*x*y1 - (3.0/2.0)*y1 + 1");
+    assert!(show(Syntax::Latex) == "2 x^{2} y_{1} - \\frac{3}{2} y_{1} + 1");
+    assert!(show(Syntax::Smt) == "(+ (* 2 x x y1) (* (- (/ 3 2)) y1) 1)");
+    assert!(Printer::new(Syntax::Smt, &r).poly(&(px.clone() - py.clone())) == "(+ x (* (- 1) y1))");
+    assert!(format!("{}", Poly::zero(&r)) == "0");
+    // テンプレートと 1 次式. 名前のないパラメーター a[i] は C では a_i
+    let t = Temp::most_gen(1, &r);
+    let pars = t.get_pars();
+    r.borrow_mut().name_par(pars[0], "c0".to_string());
+    let pr = Printer::new(Syntax::C, &r);
+    println!("{}", pr.temp(&t));
+    assert!(pr.temp(&t) == format!("a_{}*x + a_{}*y1 + c0", pars[2].id, pars[1].id));
+    let l = LinExp::from(pars[1]) * C::new(-2, 1) + LinExp::one();
+    assert!(pr.lin(&l) == format!("1 - 2*a_{}", pars[1].id));
+    assert!(Printer::new(Syntax::Latex, &r).par(pars[1]) == format!("a_{{{}}}", pars[1].id));
+    // 係数が 2 項以上のテンプレート
+    let mut t2 = t.clone() * px.clone();
+    t2 += t.clone() * c(1, 1);
+    let s = format!("{}", t2);
+    println!("{}", s);
+    assert!(s.contains(&format!("(c0 + a[{}])*x", pars[2].id)));
+    // イデアルと等式
+    let i = Ideal::new(vec![py.clone(), px.clone() - c(1, 1)], &r);
+    assert!(format!("{}", i) == "<x - 1, y1>");
+    assert!(Printer::new(Syntax::Smt, &r).ideal(&i) == "(and (= (+ x (- 1)) 0) (= y1 0))");
+    assert!(Printer::new(Syntax::C, &r).ideal(&i) == "x - 1 == 0 && y1 == 0");
+    assert!(
+        Printer::new(Syntax::Plain, &r).equation(&l, C::new(3, 1))
+            == format!("1 - 2*a[{}] = 3", pars[1].id)
+    );
+}
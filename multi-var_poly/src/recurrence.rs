@@ -0,0 +1,378 @@
+use super::coef::*;
+use super::expr::*;
+#[allow(unused_imports)]
+use super::expr_parse::*;
+use super::groebner::*;
+use super::ideal::*;
+use super::mon::*;
+use super::poly::*;
+use super::ring::*;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+
+/*
+Solvable Loops
+*/
+// ループ本体が代入だけで, 各変数の更新が x' = a x + p (a は 0 でない定数, p は先に決まる変数の多項式)
+// の形に並べられるとき, n 回目の値を n と b^n の多項式で閉じた形に書ける.
+// 入口の値と n, b^n を消去すれば, 次数を決めずにループ頭の不変式が全部求まる
+
+// ループ本体を同時代入にまとめる. 代入以外を含めば None
+pub fn body_map(e: &Expr, mut map: HashMap<Var, Poly>) -> Option<HashMap<Var, Poly>> {
+    let get = |map: &HashMap<Var, Poly>, p: &Poly| p.subs_many(map);
+    match e {
+        Expr::Ass { lv, rv } => {
+            let p = get(&map, rv);
+            map.insert(*lv, p);
+            Some(map)
+        }
+        Expr::ParAss { lvs, rvs } => {
+            let ps: Vec<Poly> = rvs.iter().map(|p| get(&map, p)).collect();
+            map.extend(lvs.iter().copied().zip(ps));
+            Some(map)
+        }
+        Expr::Skip => Some(map),
+        Expr::Seq { exprs } => exprs.iter().try_fold(map, |m, e| body_map(e, m)),
+        _ => None,
+    }
+}
+
+// x' = a x + p に分ける. a が定数でなければ None
+fn split_affine(f: &Poly, x: Var) -> Option<(C, Poly)> {
+    let cs = f.coeffs_in(x);
+    match cs.len() {
+        1 => Some((C::zero(), cs[0].clone())),
+        2 if cs[1].get_vars().is_empty() => Some((cs[1].eval(&HashMap::new()), cs[0].clone())),
+        _ => None,
+    }
+}
+
+// Σ_{k<n} k^j q^k = q^n P(n) - P(0) となる P の係数 (低い次数から).
+// q P(n+1) - P(n) = n^j を上の次数から解く. q = 1 なら次数 j + 1 で P(0) = 0
+fn sum_poly(j: usize, q: C) -> Vec<C> {
+    let binom = |i: usize, l: usize| {
+        (0..l).fold(C::one(), |acc, t| {
+            acc * C::from_integer((i - t) as i64) / C::from_integer(t as i64 + 1)
+        })
+    };
+    let one = |l: usize| if l == j { C::one() } else { C::zero() };
+    if q == C::one() {
+        let mut p = vec![C::zero(); j + 2];
+        for l in (0..=j).rev() {
+            let s = (l + 2..=j + 1).fold(C::zero(), |acc, i| acc + p[i] * binom(i, l));
+            p[l + 1] = (one(l) - s) / C::from_integer(l as i64 + 1);
+        }
+        p
+    } else {
+        let mut p = vec![C::zero(); j + 1];
+        for l in (0..=j).rev() {
+            let s = (l + 1..=j).fold(C::zero(), |acc, i| acc + p[i] * binom(i, l));
+            p[l] = (one(l) - q * s) / (q - C::one());
+        }
+        p
+    }
+}
+
+// n の冪と, b^n の補助変数の冪
+type ExpKey = (usize, Vec<(Var, usize)>);
+
+// 補助変数の番号. 環の変数 N 個に対して, 入口の値 x0 が N + x, 反復回数 n が 2N, b^n が 2N + 1 + i
+struct Aux {
+    size: usize,
+    bases: Vec<C>,
+    r: RingRef,
+}
+
+impl Aux {
+    fn var(&self, i: usize) -> Poly {
+        Poly::from((Var::new(i), &self.r))
+    }
+    fn entry(&self, x: Var) -> Poly {
+        self.var(self.size + x.id)
+    }
+    fn n(&self) -> Poly {
+        self.var(2 * self.size)
+    }
+    fn is_exp(&self, v: Var) -> bool {
+        v.id > 2 * self.size
+    }
+    // b^n
+    fn exp(&mut self, b: C) -> Poly {
+        if b == C::one() {
+            return Poly::one(&self.r);
+        }
+        let i = match self.bases.iter().position(|c| *c == b) {
+            Some(i) => i,
+            None => {
+                self.bases.push(b);
+                self.bases.len() - 1
+            }
+        };
+        self.var(2 * self.size + 1 + i)
+    }
+    // n の多項式として係数を並べたもの
+    fn poly_in_n(&self, cs: &[C]) -> Poly {
+        cs.iter()
+            .enumerate()
+            .fold(Poly::zero(&self.r), |acc, (i, c)| {
+                acc + Poly::from((*c, &self.r)) * self.n().pow(i)
+            })
+    }
+    // 底どうしの乗法的な関係. 小さい冪だけを見る
+    fn relations(&self) -> Vec<Poly> {
+        let mut rels = vec![];
+        let u = |i: usize| self.var(2 * self.size + 1 + i);
+        let one = Poly::one(&self.r);
+        for (i, a) in self.bases.iter().enumerate() {
+            if *a * *a == C::one() {
+                rels.push(u(i).pow(2) - one.clone());
+            }
+            for (j, b) in self.bases.iter().enumerate().skip(i + 1) {
+                'found: for p in 1..=6 {
+                    for q in 1..=6 {
+                        let (ap, bq) = (num_traits::pow(*a, p), num_traits::pow(*b, q));
+                        if ap == bq {
+                            rels.push(u(i).pow(p) - u(j).pow(q));
+                            break 'found;
+                        }
+                        if ap * bq == C::one() {
+                            rels.push(u(i).pow(p) * u(j).pow(q) - one.clone());
+                            break 'found;
+                        }
+                    }
+                }
+            }
+        }
+        rels
+    }
+    // b^n の部分を取り出す. 単項式の底の値と, 残り
+    fn split_exp(&self, m: &Mon<C>) -> (usize, Vec<(Var, usize)>, Mon<C>) {
+        let j = *m.vars.get(&Var::new(2 * self.size)).unwrap_or(&0);
+        let mut e: Vec<(Var, usize)> = m
+            .vars
+            .iter()
+            .filter(|(v, _)| self.is_exp(**v))
+            .map(|(v, d)| (*v, *d))
+            .collect();
+        e.sort();
+        let rest: HashMap<Var, usize> = m
+            .vars
+            .iter()
+            .filter(|(v, _)| v.id < 2 * self.size)
+            .map(|(v, d)| (*v, *d))
+            .collect();
+        let mut rest: Mon<C> = Mon::from((rest, &self.r));
+        rest.coef = m.coef;
+        (j, e, rest)
+    }
+    fn base_of(&self, e: &[(Var, usize)]) -> C {
+        e.iter().fold(C::one(), |acc, (v, d)| {
+            acc * num_traits::pow(self.bases[v.id - 2 * self.size - 1], *d)
+        })
+    }
+    // x_{n+1} = a x_n + f(n) を解く. x_n = a^n x_0 + Σ_{k<n} a^{n-1-k} f(k)
+    fn solve(&mut self, x: Var, a: C, f: &Poly) -> Poly {
+        let mut res = self.exp(a) * self.entry(x);
+        // n^j b^n ごとにまとめる
+        let mut groups: Vec<(ExpKey, Vec<Mon<C>>)> = vec![];
+        for Reverse(m) in &f.mons {
+            if m.coef.is_zero() {
+                continue;
+            }
+            let (j, e, rest) = self.split_exp(m);
+            match groups.iter_mut().find(|(k, _)| *k == (j, e.clone())) {
+                Some((_, ms)) => ms.push(rest),
+                None => groups.push(((j, e), vec![rest])),
+            }
+        }
+        for ((j, e), ms) in groups {
+            let c = Poly::from((ms, &self.r)) * Poly::from((C::one() / a, &self.r));
+            let bn = Poly::from((vec![Mon::from((e.clone(), &self.r))], &self.r));
+            let b = self.base_of(&e);
+            if b == a {
+                // a^{n-1} Σ_{k<n} k^j
+                res += c * bn * self.poly_in_n(&sum_poly(j, C::one()));
+            } else {
+                // a^{n-1} (q^n P(n) - P(0)), q = b / a
+                let p = sum_poly(j, b / a);
+                let p0 = Poly::from((p[0], &self.r));
+                res += c * (bn * self.poly_in_n(&p) - self.exp(a) * p0);
+            }
+        }
+        res
+    }
+}
+
+// 更新される変数を, 依存する変数が先に来るように並べる. 循環すれば None
+fn order_updates(upd: &HashMap<Var, (C, Poly)>) -> Option<Vec<Var>> {
+    let mut done: Vec<Var> = vec![];
+    let mut rest: Vec<Var> = upd.keys().copied().collect();
+    rest.sort();
+    while !rest.is_empty() {
+        let i = rest.iter().position(|x| {
+            upd[x]
+                .1
+                .get_vars()
+                .iter()
+                .all(|v| !upd.contains_key(v) || done.contains(v))
+        })?;
+        done.push(rest.remove(i));
+    }
+    Some(done)
+}
+
+// n 回目の各変数の値. 補助変数 (入口の値, n, b^n) の多項式
+fn closed_forms(map: &HashMap<Var, Poly>, aux: &mut Aux) -> Option<HashMap<Var, Poly>> {
+    let mut upd = HashMap::new();
+    for (x, f) in map {
+        let (a, p) = split_affine(f, *x)?;
+        if a.is_zero() {
+            return None;
+        }
+        upd.insert(*x, (a, p));
+    }
+    let mut cfs: HashMap<Var, Poly> = HashMap::new();
+    for i in 0..aux.size {
+        let x = Var::new(i);
+        if !upd.contains_key(&x) {
+            cfs.insert(x, aux.entry(x));
+        }
+    }
+    for x in order_updates(&upd)? {
+        let (a, p) = &upd[&x];
+        let f = p.subs_many(&cfs);
+        let cf = aux.solve(x, *a, &f);
+        cfs.insert(x, cf);
+    }
+    Some(cfs)
+}
+
+// 入口で entry が成り立つときの, ループ頭の不変式のイデアル. 本体が解けなければ None
+pub fn loop_invariant(body: &Expr, entry: &Ideal) -> Result<Option<Ideal>, CoefOverflow> {
+    let r = &entry.r;
+    match closed_form_gens(body, entry) {
+        Some(gens) => Ok(Some(Ideal::new(eliminate_beyond(&gens, r)?, r))),
+        None => Ok(None),
+    }
+}
+
+// 入口の値を補助変数に置いて, 閉じた形と入口の条件を並べたもの
+fn closed_form_gens(body: &Expr, entry: &Ideal) -> Option<Vec<Poly>> {
+    let r = &entry.r;
+    let map = body_map(body, HashMap::new())?;
+    let mut aux = Aux {
+        size: r.borrow().var_list().len(),
+        bases: vec![],
+        r: r.clone(),
+    };
+    let cfs = closed_forms(&map, &mut aux)?;
+    let to_entry: HashMap<Var, Poly> = (0..aux.size)
+        .map(|i| (Var::new(i), aux.entry(Var::new(i))))
+        .collect();
+    let mut gens: Vec<Poly> = entry.gens.iter().map(|g| g.subs_many(&to_entry)).collect();
+    for i in 0..aux.size {
+        let x = Var::new(i);
+        gens.push(Poly::from((x, r)) - cfs[&x].clone());
+    }
+    gens.extend(aux.relations());
+    Some(gens)
+}
+
+#[test]
+fn check_sum_poly() {
+    // Σ_{k<n} k = n(n-1)/2, Σ_{k<n} k 2^k = 2^n (n - 2) + 2
+    assert!(sum_poly(1, C::one()) == vec![C::zero(), C::new(-1, 2), C::new(1, 2)]);
+    assert!(sum_poly(1, C::new(2, 1)) == vec![C::new(-2, 1), C::one()]);
+    for (j, q) in [(0, C::new(3, 1)), (2, C::one()), (2, C::new(-1, 2))] {
+        let p = sum_poly(j, q);
+        let ev = |n: i64| {
+            p.iter().enumerate().fold(C::zero(), |acc, (i, c)| {
+                acc + *c * num_traits::pow(C::from_integer(n), i)
+            })
+        };
+        for n in 0..6 {
+            let lhs = (0..n).fold(C::zero(), |acc, k| {
+                acc + num_traits::pow(C::from_integer(k), j) * num_traits::pow(q, k as usize)
+            });
+            assert!(lhs == num_traits::pow(q, n as usize) * ev(n) - ev(0));
+        }
+    }
+}
+
+#[test]
+fn recurrence_falling_body() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let e = program()
+        .parse(
+            r#"
+            x = x0; v = v0; t = t0;
+            while (t - a != 0) {
+                x = x + v * dt;
+                v = v - g * dt;
+                t = t + dt;
+            }
+            "#,
+        )
+        .map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let v = |s: &str| Poly::from((r.borrow().var_by_name(s).unwrap(), &r));
+    let c = |n, d| Poly::from((C::new(n, d), &r));
+    let (init, body) = match &e {
+        Expr::Seq { exprs } => match &exprs[3] {
+            Expr::While { c, .. } => (
+                Expr::Seq {
+                    exprs: exprs[..3].to_vec(),
+                },
+                c.clone(),
+            ),
+            _ => panic!("while expected"),
+        },
+        _ => panic!("sequence expected"),
+    };
+    let entry = super::forward::forward(&init, Ideal::zero(&r), 1, &mut vec![]).unwrap();
+    let inv = loop_invariant(&body, &entry)
+        .unwrap()
+        .expect("solvable loop");
+    println!("{:?}", inv);
+    // v - v0 = -g (t - t0)
+    assert!(inv
+        .contains(&(v("v") - v("v0") + v("g") * (v("t") - v("t0"))))
+        .unwrap());
+    // オイラー法の位置: 2 g (x - x0) = v0^2 - v^2 + g dt (v0 - v)
+    let p = c(2, 1) * v("g") * (v("x") - v("x0")) - v("v0").pow(2) + v("v").pow(2)
+        - v("g") * v("dt") * (v("v0") - v("v"));
+    assert!(inv.contains(&p).unwrap());
+    assert!(!inv.contains(&(v("t") - v("t0"))).unwrap());
+}
+
+#[test]
+fn recurrence_exponential() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let parse = |s: &str| {
+        let e = program().parse(s).map_or(E::Skip, |(_i, c)| c);
+        convert_from_parseresult(&e, &r)
+    };
+    let body = parse("x = 2 * x; y = 4 * y; s = s + x; ");
+    let v = |s: &str| Poly::from((r.borrow().var_by_name(s).unwrap(), &r));
+    let one = Poly::one(&r);
+    let entry = Ideal::new(vec![v("x") - one.clone(), v("y") - one.clone(), v("s")], &r);
+    let inv = loop_invariant(&body, &entry)
+        .unwrap()
+        .expect("solvable loop");
+    println!("{:?}", inv);
+    // y = x^2, s = 2 (x - 1) (s は更新後の x を足す)
+    assert!(inv.contains(&(v("y") - v("x").pow(2))).unwrap());
+    assert!(inv
+        .contains(&(v("s") - Poly::from((C::new(2, 1), &r)) * (v("x") - one.clone())))
+        .unwrap());
+    // 係数が変数なら解けない
+    assert!(loop_invariant(&parse("x = y * x; "), &entry)
+        .unwrap()
+        .is_none());
+    // 循環する更新も扱わない
+    assert!(loop_invariant(&parse("x = x + y; y = y + x; "), &entry)
+        .unwrap()
+        .is_none());
+}
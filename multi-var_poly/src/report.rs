@@ -0,0 +1,238 @@
+use super::coef::*;
+use super::constraints::*;
+use super::expr::*;
+#[allow(unused_imports)]
+use super::expr_parse::*;
+use super::ideal::*;
+use super::poly::*;
+use super::ring::*;
+use super::temp::*;
+use serde::Serialize;
+use std::time::Instant;
+
+/*
+Analysis Report
+*/
+// 後ろ向きの制約生成から解までを一回走らせた結果. CI で比べられるよう JSON にできる
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MonReport {
+    pub num: i64,
+    pub den: i64,
+    // 変数名と次数. Var の番号順
+    pub vars: Vec<(String, usize)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct InvReport {
+    // inv = 0 の左辺
+    pub text: String,
+    pub degree: usize,
+    pub monomials: Vec<MonReport>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct LoopReport {
+    // ソースに現れる順 (先行順) の番号
+    pub index: usize,
+    pub invariants: Vec<InvReport>,
+    // 不変式の零点集合の (環の変数全部の空間での) 次元. 零点がなければ None
+    pub dim: Option<usize>,
+    // 検証条件を作るときのために多項式のまま持っておく
+    #[serde(skip)]
+    pub polys: Vec<Poly>,
+    // 解を代入する前のループ頭のイデアル. CAS に渡すため
+    #[serde(skip)]
+    pub head: PIdeal,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    // テンプレートの係数の数
+    pub template: usize,
+    // 商の分も含めたパラメーターの数
+    pub parameters: usize,
+    pub equations: usize,
+    pub rank: usize,
+    // 解に残った自由なパラメーターの数
+    pub free: usize,
+    pub divisions: usize,
+    pub fresh: usize,
+    // 解く前の前処理で減った式
+    pub reduction: Reduction,
+    pub millis: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Report {
+    pub mode: RemMode,
+    pub degree: usize,
+    pub solved: bool,
+    pub loops: Vec<LoopReport>,
+    pub stats: Stats,
+    // 解いた連立方程式. ループ全部で一つ
+    #[serde(skip)]
+    pub equations: LinearEquations,
+}
+
+impl Report {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("report is serializable")
+    }
+}
+
+impl From<&Poly> for InvReport {
+    fn from(p: &Poly) -> Self {
+        let monomials = p
+            .mons
+            .iter()
+            .map(|m| {
+                let mut vs: Vec<(&Var, &usize)> = m.0.vars.iter().collect();
+                vs.sort();
+                let r = p.r.borrow();
+                MonReport {
+                    num: *m.0.coef.numer(),
+                    den: *m.0.coef.denom(),
+                    vars: vs
+                        .into_iter()
+                        .map(|(v, d)| (r.vars[v].clone(), *d))
+                        .collect(),
+                }
+            })
+            .collect();
+        InvReport {
+            text: format!("{}", p),
+            degree: p.tdeg(),
+            monomials,
+        }
+    }
+}
+
+// 次数 d の一般テンプレートで不変式を求める. 途中で作ったパラメーターは返る前に解放する
+pub fn analyze(e: &Expr, d: usize, mode: RemMode, r: &RingRef) -> Result<Report, CoefOverflow> {
+    let start = Instant::now();
+    let _scope = r.par_scope();
+    let g = Temp::most_gen(d, r);
+    let mut gs = GenStats::default();
+    let (i, c) = gen_con_with(e, PIdeal::from(g.clone()), Cs::new(), mode, &mut gs);
+    let c = c.add(Constraint(i, PIdeal::zero(r)));
+    let mut le = LinearEquations::from((c, r));
+    let sol = le.solve_incremental();
+    let mut stats = Stats {
+        template: g.get_pars().len(),
+        parameters: le.num_pars(),
+        equations: le.num_eqs(),
+        rank: le.rank().unwrap_or(0),
+        divisions: gs.divisions,
+        fresh: gs.fresh,
+        reduction: le.simplify().2,
+        ..Stats::default()
+    };
+    gs.heads.reverse();
+    let loops = gs
+        .heads
+        .iter()
+        .enumerate()
+        .map(|(index, head)| {
+            let mut cands: Vec<Poly> = vec![];
+            if let Some(sol) = &sol {
+                let mut ts: Vec<&Temp> = head.gens.iter().collect();
+                ts.sort();
+                for t in ts {
+                    cands.extend(t.subs_pars(sol.clone()).components());
+                }
+            }
+            // 解空間の基底には (2x - y)^2 と 2x - y のように重なるものがある.
+            // 次数の低い順に見て, それまでのイデアルに入るものは落とす
+            cands.sort_by_key(|p| p.tdeg());
+            let mut invs: Vec<Poly> = vec![];
+            for p in cands {
+                if !Ideal::new(invs.clone(), r).contains(&p)? {
+                    invs.push(p);
+                }
+            }
+            Ok(LoopReport {
+                index,
+                invariants: invs.iter().map(InvReport::from).collect(),
+                dim: Ideal::new(invs.clone(), r).dim()?,
+                polys: invs,
+                head: head.clone(),
+            })
+        })
+        .collect::<Result<Vec<LoopReport>, CoefOverflow>>()?;
+    if let Some(sol) = &sol {
+        stats.free = g.subs_pars(sol.clone()).get_pars().len();
+    }
+    stats.millis = start.elapsed().as_millis() as u64;
+    Ok(Report {
+        mode,
+        degree: d,
+        solved: sol.is_some(),
+        loops,
+        stats,
+        equations: le,
+    })
+}
+
+#[test]
+fn report_json() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let e = program()
+        .parse(
+            r#"
+            x = 0; y = 0;
+            while (x - n != 0) {
+                x = x + 1;
+                y = y + 2;
+            }
+            z = 0;
+            while (z - y != 0) {
+                z = z + 1;
+            }
+            "#,
+        )
+        .map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let rep = analyze(&e, 1, RemMode::Quotient, &r).unwrap();
+    let json = rep.to_json();
+    println!("{}", json);
+    assert!(rep.solved && rep.loops.len() == 2);
+    assert!(rep.stats.template == 5 && rep.stats.rank > 0);
+    assert!(rep.stats.equations > 0 && rep.stats.free > 0);
+    // 一つ目のループでは 2x - y = 0
+    let y2x = &rep.loops[0].invariants;
+    assert!(y2x.iter().any(|inv| inv.degree == 1
+        && inv.monomials.len() == 2
+        && inv
+            .monomials
+            .iter()
+            .any(|m| m.vars == vec![("x".to_string(), 1)] && m.num.abs() == 2 && m.den == 1)));
+    // パラメーターは残らない
+    assert!(r.borrow().pars.is_empty());
+    let v: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert!(v["mode"] == "quotient");
+    assert!(v["loops"][0]["index"] == 0);
+    assert!(v["loops"][0]["invariants"][0]["monomials"][0]["vars"].is_array());
+    // x, y, n, z の空間で, 2x - y = 0 は 3 次元
+    println!("{:?}", rep.loops.iter().map(|l| l.dim).collect::<Vec<_>>());
+    assert!(rep.loops[0].dim == Some(3));
+    assert!(v["loops"][0]["dim"] == 3);
+    assert!(rep.equations.num_eqs() == rep.stats.equations);
+    assert!(rep.loops.iter().all(|l| !l.head.gens.is_empty()));
+    let red = &rep.stats.reduction;
+    assert!(red.rows_before == rep.stats.equations && red.rows_after <= red.rows_before);
+    assert!(v["stats"]["reduction"]["rows_before"] == rep.stats.equations);
+    let rep = analyze(&e, 1, RemMode::NormalForm, &r).unwrap();
+    assert!(rep.stats.fresh == 0);
+    assert!(rep.to_json().contains("\"normal-form\""));
+    // 次数 2 でも 2x - y と (2x - y)^2 の両方は出さない
+    let rep = analyze(&e, 2, RemMode::Quotient, &r).unwrap();
+    let texts: Vec<&str> = rep.loops[0]
+        .invariants
+        .iter()
+        .map(|i| &i.text[..])
+        .collect();
+    println!("{:?}", texts);
+    assert!(texts == vec!["2*x - y"]);
+}
@@ -31,15 +31,18 @@ impl std::fmt::Debug for Par {
     }
 }
 
-use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
-use std::rc::Rc;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 #[derive(Eq, PartialEq, Clone, Debug)]
 pub struct Ring {
     pub vars: HashMap<Var, String>,
     revvars: HashMap<String, Var>,
     pub pars: HashSet<Par>,
+    // 出力用の名前. 名前のないパラメーターは a[i] と表示する
+    par_names: HashMap<Par, String>,
+    // 各スコープを開いた時点のパラメーター数
+    scopes: Vec<usize>,
 }
 impl Hash for Ring {
     fn hash<H: Hasher>(&self, state: &mut H) {
@@ -58,16 +61,71 @@ impl Hash for Ring {
 }
 
 impl Ring {
-    pub fn new() -> Rc<RefCell<Ring>> {
-        Rc::new(RefCell::new(Ring {
+    pub fn new() -> RingRef {
+        RingRef::from(Ring {
             vars: HashMap::new(),
             revvars: HashMap::new(),
             pars: HashSet::new(),
-        }))
+            par_names: HashMap::new(),
+            scopes: vec![],
+        })
     }
     pub fn pextend(&mut self, new_pars: Vec<Par>) {
         self.pars.extend(new_pars);
     }
+    // 次に割り当てるパラメーターの番号. pars は解放されると縮む
+    pub fn par_count(&self) -> usize {
+        self.pars.iter().map(|p| p.id + 1).max().unwrap_or(0)
+    }
+    // 数えてから拡大するまでの間に他のスレッドが割り込まないよう, 1回の借用で確保する
+    pub fn fresh_pars(&mut self, n: usize) -> Vec<Par> {
+        let cnt = self.par_count();
+        let new_pars: Vec<Par> = (cnt..cnt + n).map(Par::new).collect();
+        self.pextend(new_pars.clone());
+        new_pars
+    }
+    pub fn name_par(&mut self, p: Par, s: String) {
+        self.par_names.insert(p, s);
+    }
+    pub fn par_name(&self, p: Par) -> String {
+        match self.par_names.get(&p) {
+            Some(s) => s.clone(),
+            None => format!("{:?}", p),
+        }
+    }
+
+    // ここから先に割り当てたパラメーターは, pop_par_scope でまとめて解放できる
+    pub fn push_par_scope(&mut self) {
+        let cnt = self.par_count();
+        self.scopes.push(cnt);
+    }
+    pub fn pop_par_scope(&mut self) -> Vec<Par> {
+        let mark = self.scopes.pop().expect("no parameter scope to pop");
+        let mut released: Vec<Par> = self.pars.iter().filter(|p| p.id >= mark).copied().collect();
+        released.sort();
+        for p in &released {
+            self.pars.remove(p);
+            self.par_names.remove(p);
+        }
+        released
+    }
+    pub fn par_scope_depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    pub fn var_by_name(&self, s: &str) -> Option<Var> {
+        self.revvars.get(s).copied()
+    }
+    // 変数を Var の番号順に並べた名前の列
+    pub fn var_names(&self) -> Vec<String> {
+        let mut v: Vec<(&Var, &String)> = self.vars.iter().collect();
+        v.sort();
+        v.into_iter().map(|(_, s)| s.clone()).collect()
+    }
+    // 変数を番号順に並べたもの. 勾配などの並びに使う
+    pub fn var_list(&self) -> Vec<Var> {
+        (0..self.vars.len()).map(Var::new).collect()
+    }
     pub fn vextend(&mut self, s: String) -> Var {
         if self.revvars.contains_key(&s) {
             self.revvars[&s]
@@ -79,3 +137,280 @@ impl Ring {
         }
     }
 }
+
+/*
+Ring Compatibility
+*/
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct RingMismatch {
+    pub left: Vec<String>,
+    pub right: Vec<String>,
+}
+
+impl std::fmt::Display for RingMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "operands belong to different rings: Q[{}] and Q[{}]",
+            self.left.join(", "),
+            self.right.join(", ")
+        )
+    }
+}
+
+impl std::fmt::Debug for RingMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl std::error::Error for RingMismatch {}
+
+// 2つの環を, 変数名で対応させて1つにまとめたもの
+#[derive(Clone, Debug)]
+pub struct RingMerge {
+    pub ring: RingRef,
+    pub left: HashMap<Var, Var>,
+    pub right: HashMap<Var, Var>,
+    // パラメーターは名前ではなく番号で区別するので, 別の環のものは別の未知数
+    pub left_pars: HashMap<Par, Par>,
+    pub right_pars: HashMap<Par, Par>,
+}
+
+impl Ring {
+    // 左の環の番号付けはそのまま, 右の環にしかない変数を後ろに足す.
+    // 右の環のパラメーターは左と番号が重なるので, 新しく取り直す
+    pub fn merge(r1: &RingRef, r2: &RingRef) -> RingMerge {
+        let mut ring = r1.borrow().clone();
+        let right = r2.borrow().clone();
+        let mut names: Vec<(Var, String)> = right.vars.into_iter().collect();
+        names.sort();
+        for (_, s) in names {
+            ring.vextend(s);
+        }
+        let left_pars: HashMap<Par, Par> = ring.pars.iter().map(|p| (*p, *p)).collect();
+        let mut rps: Vec<Par> = right.pars.iter().copied().collect();
+        rps.sort();
+        let fresh = ring.fresh_pars(rps.len());
+        let right_pars: HashMap<Par, Par> = rps.into_iter().zip(fresh).collect();
+        for (p, q) in &right_pars {
+            if let Some(s) = right.par_names.get(p) {
+                ring.name_par(*q, s.clone());
+            }
+        }
+        let ring = RingRef::from(ring);
+        let left = Ring::var_map(r1, &ring).expect("merged ring contains the left ring");
+        let right = Ring::var_map(r2, &ring).expect("merged ring contains the right ring");
+        RingMerge {
+            ring,
+            left,
+            right,
+            left_pars,
+            right_pars,
+        }
+    }
+
+    // from の各変数を, 同じ名前の to の変数に対応させる
+    pub fn var_map(from: &RingRef, to: &RingRef) -> Result<HashMap<Var, Var>, RingMismatch> {
+        let mut res = HashMap::new();
+        if from.ptr_eq(to) {
+            for v in from.borrow().vars.keys() {
+                res.insert(*v, *v);
+            }
+            return Ok(res);
+        }
+        let (f, t) = (from.borrow(), to.borrow());
+        for (v, s) in &f.vars {
+            match t.revvars.get(s) {
+                Some(w) => drop(res.insert(*v, *w)),
+                None => {
+                    return Err(RingMismatch {
+                        left: f.var_names(),
+                        right: t.var_names(),
+                    })
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+// スレッド間で共有できる環のハンドル.
+// RefCell の代わりに RwLock で守り, 呼び出し側は今までどおり borrow / borrow_mut を使う
+#[derive(Clone)]
+pub struct RingRef(Arc<RwLock<Ring>>);
+
+impl RingRef {
+    pub fn borrow(&self) -> RwLockReadGuard<'_, Ring> {
+        self.0.read().expect("ring lock poisoned")
+    }
+    pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, Ring> {
+        self.0.write().expect("ring lock poisoned")
+    }
+    pub fn ptr_eq(&self, other: &RingRef) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+    // 同じハンドルか, 変数の番号と名前が一致していれば同じ環とみなす.
+    // パラメーターは拡大され続けるので比べない
+    pub fn same_ring(&self, other: &RingRef) -> bool {
+        self.ptr_eq(other) || self.borrow().vars == other.borrow().vars
+    }
+    pub fn check(&self, other: &RingRef) -> Result<(), RingMismatch> {
+        if self.same_ring(other) {
+            Ok(())
+        } else {
+            Err(RingMismatch {
+                left: self.borrow().var_names(),
+                right: other.borrow().var_names(),
+            })
+        }
+    }
+    // 演算子から呼ぶ. リリースビルドでも検査する
+    pub fn assert_same(&self, other: &RingRef) {
+        if let Err(e) = self.check(other) {
+            panic!("{}", e);
+        }
+    }
+}
+
+// スコープを抜けると, その間に割り当てたパラメーターを解放する.
+// スコープは環全体で一つのスタックなので, 開いている間に他のスレッド (gen_con_par など) から
+// 同じ環にパラメーターを割り当てたり, スコープを開いたりしてはいけない
+pub struct ParScope {
+    r: RingRef,
+    depth: usize,
+}
+
+impl RingRef {
+    pub fn par_scope(&self) -> ParScope {
+        let mut r = self.borrow_mut();
+        r.push_par_scope();
+        ParScope {
+            r: self.clone(),
+            depth: r.par_scope_depth(),
+        }
+    }
+}
+
+impl Drop for ParScope {
+    fn drop(&mut self) {
+        // 巻き戻し中にもう一度 panic するとプロセスごと落ちるので, そのときは黙って諦める
+        let panicking = std::thread::panicking();
+        let mut r = match self.r.0.write() {
+            Ok(r) => r,
+            Err(_) if panicking => return,
+            Err(_) => panic!("ring lock poisoned"),
+        };
+        if r.par_scope_depth() != self.depth {
+            if panicking {
+                return;
+            }
+            panic!("parameter scopes must be released in LIFO order");
+        }
+        r.pop_par_scope();
+    }
+}
+
+impl From<Ring> for RingRef {
+    fn from(r: Ring) -> Self {
+        RingRef(Arc::new(RwLock::new(r)))
+    }
+}
+
+impl PartialEq for RingRef {
+    fn eq(&self, other: &RingRef) -> bool {
+        // 同じハンドルならロックを取らない (同じ RwLock を二重に読むのを避ける)
+        self.ptr_eq(other) || *self.borrow() == *other.borrow()
+    }
+}
+impl Eq for RingRef {}
+
+impl std::fmt::Debug for RingRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", *self.borrow())
+    }
+}
+
+#[test]
+fn ring_ref_is_shareable() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<RingRef>();
+
+    let r = Ring::new();
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let r = r.clone();
+            std::thread::spawn(move || r.borrow_mut().fresh_pars(5))
+        })
+        .collect();
+    let mut pars: Vec<Par> = handles
+        .into_iter()
+        .flat_map(|h| h.join().unwrap())
+        .collect();
+    pars.sort();
+    // 割り込まれずに, 重複なく確保されている
+    assert!(pars == (0..20).map(Par::new).collect::<Vec<Par>>());
+    assert!(r.borrow().pars.len() == 20);
+}
+
+#[test]
+fn ring_merge_by_name() {
+    let r1 = Ring::new();
+    let x = r1.borrow_mut().vextend("x".to_string());
+    let y = r1.borrow_mut().vextend("y".to_string());
+    let r2 = Ring::new();
+    let z = r2.borrow_mut().vextend("z".to_string());
+    let y2 = r2.borrow_mut().vextend("y".to_string());
+    assert!(r1.check(&r2).is_err());
+    assert!(r1.check(&r1.clone()).is_ok());
+
+    let m = Ring::merge(&r1, &r2);
+    assert!(m.ring.borrow().var_names() == vec!["x", "y", "z"]);
+    assert!(m.left[&x] == x && m.left[&y] == y);
+    assert!(m.right[&y2] == y);
+    assert!(m.right[&z] == Var::new(2));
+    // 右の環の変数が全部入っていないと対応は作れない
+    assert!(Ring::var_map(&m.ring, &r1).is_err());
+
+    // パラメーターはどちらも a[0] から. 右の環のものは取り直す
+    let p1 = r1.borrow_mut().fresh_pars(2);
+    let p2 = r2.borrow_mut().fresh_pars(2);
+    r2.borrow_mut().name_par(p2[1], "c".to_string());
+    let m = Ring::merge(&r1, &r2);
+    assert!(p1 == p2);
+    assert!(m.left_pars[&p1[0]] == p1[0] && m.left_pars[&p1[1]] == p1[1]);
+    assert!(m.right_pars[&p2[0]] == Par::new(2) && m.right_pars[&p2[1]] == Par::new(3));
+    assert!(m.ring.borrow().pars.len() == 4);
+    assert!(m.ring.borrow().par_name(Par::new(3)) == "c");
+}
+
+#[test]
+fn par_scopes_release() {
+    let r = Ring::new();
+    let base = r.borrow_mut().fresh_pars(3);
+    r.borrow_mut().name_par(base[0], "c".to_string());
+    {
+        let _s = r.par_scope();
+        let tmp = r.borrow_mut().fresh_pars(4);
+        assert!(tmp[0] == Par::new(3));
+        r.borrow_mut().name_par(tmp[0], "q".to_string());
+        {
+            let _s = r.par_scope();
+            r.borrow_mut().fresh_pars(2);
+            assert!(r.borrow().par_count() == 9);
+        }
+        assert!(r.borrow().par_count() == 7);
+    }
+    // スコープの外で作ったものだけが残り, 番号は再利用される
+    assert!(r.borrow().pars == base.iter().copied().collect());
+    assert!(r.borrow_mut().fresh_pars(1) == vec![Par::new(3)]);
+    assert!(r.borrow().par_name(Par::new(3)) == "a[3]");
+    assert!(r.borrow().par_name(base[0]) == "c");
+    // 順番を間違えたまま panic しても, drop でもう一度 panic して abort しない
+    let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _v = vec![r.par_scope(), r.par_scope()];
+        panic!("unwinding with scopes in the wrong order");
+    }));
+    assert!(res.is_err());
+}
@@ -0,0 +1,228 @@
+use super::coef::*;
+use super::constraints::*;
+#[allow(unused_imports)]
+use super::expr::*;
+#[allow(unused_imports)]
+use super::expr_parse::*;
+use super::ideal::*;
+use super::poly::*;
+use super::report::InvReport;
+use super::ring::*;
+use super::temp::*;
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/*
+Degree Search
+*/
+// テンプレートの次数を 1 から上げながら不変式を探す.
+// 新しい (それまでの不変式のイデアルに入らない) 不変式が出なくなるか, 予算が尽きたら止める
+
+#[derive(Clone, Debug)]
+pub struct Budget {
+    pub max_deg: usize,
+    // テンプレートの係数パラメーターの数の上限
+    pub max_pars: usize,
+    // 次の次数に進む前に確かめる. 一つの次数の途中では止めない
+    pub time: Duration,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Budget {
+            max_deg: 4,
+            max_pars: 1000,
+            time: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stop {
+    // 新しい不変式が出なくなった
+    Saturated,
+    MaxDeg,
+    MaxPars,
+    Time,
+}
+
+#[derive(Clone, Debug)]
+pub struct Found {
+    pub deg: usize,
+    pub inv: Poly,
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchReport {
+    pub invs: Vec<Found>,
+    // 最後まで調べた次数
+    pub deg: usize,
+    pub stop: Stop,
+}
+
+#[derive(Serialize)]
+struct FoundJson {
+    // 見つかったときのテンプレートの次数
+    found_at: usize,
+    #[serde(flatten)]
+    inv: InvReport,
+}
+
+#[derive(Serialize)]
+struct SearchJson {
+    degree: usize,
+    stop: Stop,
+    invariants: Vec<FoundJson>,
+}
+
+impl SearchReport {
+    pub fn ideal(&self, r: &RingRef) -> Ideal {
+        Ideal::new(self.invs.iter().map(|f| f.inv.clone()).collect(), r)
+    }
+
+    pub fn to_json(&self) -> String {
+        let j = SearchJson {
+            degree: self.deg,
+            stop: self.stop,
+            invariants: self
+                .invs
+                .iter()
+                .map(|f| FoundJson {
+                    found_at: f.deg,
+                    inv: InvReport::from(&f.inv),
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&j).expect("search report is serializable")
+    }
+}
+
+// 次数 d の一般テンプレートで見つかる不変式の基底. 解の自由なパラメーターごとに一つ
+fn invariants_at(e: &Expr, d: usize, mode: RemMode, r: &RingRef) -> Vec<Poly> {
+    let g = Temp::most_gen(d, r);
+    let (i, c) = gen_con_with(
+        e,
+        PIdeal::from(g.clone()),
+        Cs::new(),
+        mode,
+        &mut GenStats::default(),
+    );
+    let c = c.add(Constraint(i, PIdeal::zero(r)));
+    let mut le = LinearEquations::from((c, r));
+    let sol = match le.solve_incremental() {
+        Some(sol) => sol,
+        None => return vec![],
+    };
+    g.subs_pars(sol).components()
+}
+
+// 次数 d の一般テンプレートの係数の数 C(n + d, d)
+fn template_size(n: usize, d: usize) -> usize {
+    (1..=d).fold(1, |acc, i| acc * (n + i) / i)
+}
+
+pub fn search_degree(
+    e: &Expr,
+    mode: RemMode,
+    r: &RingRef,
+    budget: &Budget,
+) -> Result<SearchReport, CoefOverflow> {
+    let start = Instant::now();
+    let n = r.borrow().vars.len();
+    let mut found = Ideal::zero(r);
+    let mut invs: Vec<Found> = vec![];
+    let mut deg = 0;
+    let stop = loop {
+        let d = deg + 1;
+        if d > budget.max_deg {
+            break Stop::MaxDeg;
+        }
+        if template_size(n, d) > budget.max_pars {
+            break Stop::MaxPars;
+        }
+        if start.elapsed() > budget.time {
+            break Stop::Time;
+        }
+        // この次数で作ったパラメーターは次の次数に持ち越さない
+        let cands = {
+            let _scope = r.par_scope();
+            invariants_at(e, d, mode, r)
+        };
+        let mut new = false;
+        for f in cands {
+            if !found.contains(&f)? {
+                found = found.sum(&Ideal::new(vec![f.clone()], r));
+                invs.push(Found { deg: d, inv: f });
+                new = true;
+            }
+        }
+        deg = d;
+        // 低い次数で何も見つからないのは普通なので, 一度見つかってから止める
+        if !new && !invs.is_empty() {
+            break Stop::Saturated;
+        }
+    };
+    Ok(SearchReport { invs, deg, stop })
+}
+
+#[test]
+fn search_counter() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let e = program()
+        .parse(
+            r#"
+            x = 0; y = 0; s = 0;
+            while (x - n != 0) {
+                s = s + 2 * x + 1;
+                x = x + 1;
+                y = y + 2;
+            }
+            "#,
+        )
+        .map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let v = |s: &str| Poly::from((r.borrow().var_by_name(s).unwrap(), &r));
+    let rep = search_degree(&e, RemMode::Quotient, &r, &Budget::default()).unwrap();
+    for f in &rep.invs {
+        println!("deg {}: {:?}", f.deg, f.inv);
+    }
+    println!("{:?} at {}", rep.stop, rep.deg);
+    assert!(rep.stop == Stop::Saturated && rep.deg == 3);
+    // y = 2x は次数 1, s = x^2 は次数 2 で見つかる
+    let y = v("y") - Poly::from((C::new(2, 1), &r)) * v("x");
+    let s = v("s") - v("x").pow(2);
+    let at = |p: &Poly| {
+        rep.invs
+            .iter()
+            .position(|f| Ideal::new(vec![f.inv.clone()], &r).contains(p).unwrap())
+            .map(|i| rep.invs[i].deg)
+    };
+    assert!(at(&y) == Some(1));
+    assert!(rep.ideal(&r).contains(&s).unwrap());
+    assert!(rep.invs.iter().all(|f| f.deg <= 2));
+    let v: serde_json::Value = serde_json::from_str(&rep.to_json()).unwrap();
+    assert!(v["stop"] == "saturated" && v["degree"] == 3);
+    assert!(v["invariants"][0]["found_at"] == 1);
+    // 予算で止まる
+    let small = Budget {
+        max_deg: 1,
+        ..Budget::default()
+    };
+    let rep = search_degree(&e, RemMode::Quotient, &r, &small).unwrap();
+    assert!(rep.stop == Stop::MaxDeg && rep.deg == 1);
+    let rep = search_degree(
+        &e,
+        RemMode::Quotient,
+        &r,
+        &Budget {
+            max_pars: 10,
+            ..Budget::default()
+        },
+    )
+    .unwrap();
+    // 変数 4 つで次数 2 の係数は 15 個
+    assert!(rep.stop == Stop::MaxPars && rep.deg == 1);
+    assert!(r.borrow().pars.is_empty());
+}
@@ -0,0 +1,293 @@
+use super::coef::*;
+use super::expr::*;
+#[allow(unused_imports)]
+use super::expr_parse::*;
+use super::poly::*;
+use super::print::*;
+use super::ring::*;
+use std::collections::HashMap;
+
+/*
+SMT-LIB Verification Conditions
+*/
+// 求めた不変式を外部のソルバーで確かめるためのスクリプト.
+// ループごとに, 最初に入るときに成り立つこと (initiation) と本体で保たれること (consecution) を
+// 最弱前条件で書き, その否定が unsat になるかを問う.
+// 他のループは不変式と出口の条件だけで要約するので, どのスクリプトも他の不変式を仮定している
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Logic {
+    Int,
+    Real,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VcKind {
+    Initiation,
+    Consecution,
+}
+
+#[derive(Clone, Debug)]
+pub struct Vc {
+    // ソースに現れる順 (先行順) のループの番号
+    pub index: usize,
+    pub kind: VcKind,
+    pub script: String,
+}
+
+#[derive(Clone, Debug)]
+enum Form {
+    True,
+    // p = 0
+    Eq(Poly),
+    // p != 0
+    Ne(Poly),
+    And(Vec<Form>),
+    Imp(Box<Form>, Box<Form>),
+}
+
+impl Form {
+    fn and(fs: Vec<Form>) -> Form {
+        let mut fs: Vec<Form> = fs.into_iter().filter(|f| !f.is_true()).collect();
+        match fs.len() {
+            0 => Form::True,
+            1 => fs.pop().unwrap(),
+            _ => Form::And(fs),
+        }
+    }
+    fn imp(a: Form, b: Form) -> Form {
+        if b.is_true() {
+            Form::True
+        } else {
+            Form::Imp(Box::new(a), Box::new(b))
+        }
+    }
+    fn is_true(&self) -> bool {
+        matches!(self, Form::True)
+    }
+    fn guard(g: &Pred, holds: bool) -> Form {
+        if g.eq == holds {
+            Form::Eq(g.p.clone())
+        } else {
+            Form::Ne(g.p.clone())
+        }
+    }
+    fn subs(&self, map: &HashMap<Var, Poly>) -> Form {
+        match self {
+            Form::True => Form::True,
+            Form::Eq(p) => Form::Eq(p.subs_many(map)),
+            Form::Ne(p) => Form::Ne(p.subs_many(map)),
+            Form::And(fs) => Form::And(fs.iter().map(|f| f.subs(map)).collect()),
+            Form::Imp(a, b) => Form::Imp(Box::new(a.subs(map)), Box::new(b.subs(map))),
+        }
+    }
+    fn smt(&self, pr: &Printer, logic: Logic) -> Result<String, CoefOverflow> {
+        // 整数では係数を整数にそろえる. 定数倍しても = 0 は変わらない.
+        // 分母の lcm が大きいと, そろえた係数が i64 に収まらないことがある
+        let poly = |p: &Poly| match logic {
+            Logic::Int => Ok(pr.poly(&p.try_primitive_part()?)),
+            Logic::Real => Ok(pr.poly(p)),
+        };
+        Ok(match self {
+            Form::True => "true".to_string(),
+            Form::Eq(p) => format!("(= {} 0)", poly(p)?),
+            Form::Ne(p) => format!("(not (= {} 0))", poly(p)?),
+            Form::And(fs) => {
+                let fs = fs
+                    .iter()
+                    .map(|f| f.smt(pr, logic))
+                    .collect::<Result<Vec<String>, CoefOverflow>>()?;
+                format!("(and {})", fs.join(" "))
+            }
+            Form::Imp(a, b) => format!("(=> {} {})", a.smt(pr, logic)?, b.smt(pr, logic)?),
+        })
+    }
+}
+
+fn count_loops(e: &Expr) -> usize {
+    match e {
+        Expr::Seq { exprs } => exprs.iter().map(count_loops).sum(),
+        Expr::If { the, els, .. } => count_loops(the) + count_loops(els),
+        Expr::While { c, .. } => 1 + count_loops(c),
+        _ => 0,
+    }
+}
+
+struct Ctx<'a> {
+    invs: &'a [Vec<Poly>],
+    target: usize,
+    kind: VcKind,
+    // post と無関係に成り立つべき条件
+    obls: Vec<Form>,
+}
+
+impl<'a> Ctx<'a> {
+    fn inv(&self, k: usize) -> Form {
+        Form::and(self.invs[k].iter().cloned().map(Form::Eq).collect())
+    }
+
+    // e の後で post が成り立つための条件. base は e の中の最初のループの番号
+    fn wp(&mut self, e: &Expr, post: Form, base: usize) -> Form {
+        match e {
+            Expr::Ass { lv, rv } => {
+                let map = vec![(*lv, rv.clone())].into_iter().collect();
+                post.subs(&map)
+            }
+            Expr::ParAss { lvs, rvs } => {
+                let map = lvs.iter().copied().zip(rvs.iter().cloned()).collect();
+                post.subs(&map)
+            }
+            Expr::Skip => post,
+            Expr::Seq { exprs } => {
+                let mut bases = vec![];
+                let mut b = base;
+                for e in exprs {
+                    bases.push(b);
+                    b += count_loops(e);
+                }
+                exprs
+                    .iter()
+                    .zip(bases)
+                    .rev()
+                    .fold(post, |q, (e, b)| self.wp(e, q, b))
+            }
+            Expr::If { guard, the, els } => {
+                let b = base + count_loops(the);
+                let w1 = self.wp(the, post.clone(), base);
+                let w2 = self.wp(els, post, b);
+                Form::and(vec![
+                    Form::imp(Form::guard(guard, true), w1),
+                    Form::imp(Form::guard(guard, false), w2),
+                ])
+            }
+            Expr::While { guard, c } => {
+                let inv = self.inv(base);
+                let enter = Form::and(vec![inv.clone(), Form::guard(guard, true)]);
+                if base == self.target {
+                    return match self.kind {
+                        VcKind::Initiation => inv,
+                        VcKind::Consecution => {
+                            let w = self.wp(c, inv, base + 1);
+                            self.obls.push(Form::imp(enter, w));
+                            Form::True
+                        }
+                    };
+                }
+                // 対象のループが本体の中にあるときだけ本体に入る
+                if base < self.target && self.target <= base + count_loops(c) {
+                    let w = self.wp(c, Form::True, base + 1);
+                    self.obls.push(Form::imp(enter, w));
+                }
+                let exit = Form::and(vec![inv, Form::guard(guard, false)]);
+                self.obls.push(Form::imp(exit, post));
+                Form::True
+            }
+        }
+    }
+}
+
+// invs[k] は k 番目のループの不変式 (どれも = 0). ループごとに initiation, consecution の順に返す
+pub fn smt_vcs(
+    e: &Expr,
+    invs: &[Vec<Poly>],
+    logic: Logic,
+    r: &RingRef,
+) -> Result<Vec<Vc>, CoefOverflow> {
+    assert!(invs.len() == count_loops(e), "one invariant set per loop");
+    let pr = Printer::new(Syntax::Smt, r);
+    let (logic_name, sort) = match logic {
+        Logic::Int => ("QF_NIA", "Int"),
+        Logic::Real => ("QF_NRA", "Real"),
+    };
+    let decls: Vec<String> = r
+        .borrow()
+        .var_list()
+        .into_iter()
+        .map(|v| format!("(declare-const {} {})", pr.var(v), sort))
+        .collect();
+    let mut res = vec![];
+    for index in 0..invs.len() {
+        for kind in [VcKind::Initiation, VcKind::Consecution] {
+            let mut ctx = Ctx {
+                invs,
+                target: index,
+                kind,
+                obls: vec![],
+            };
+            let pre = ctx.wp(e, Form::True, 0);
+            ctx.obls.push(pre);
+            let vc = Form::and(ctx.obls);
+            let name = match kind {
+                VcKind::Initiation => "initiation",
+                VcKind::Consecution => "consecution",
+            };
+            let script = format!(
+                "; loop {}: {}\n(set-logic {})\n{}\n(assert (not {}))\n(check-sat)\n",
+                index,
+                name,
+                logic_name,
+                decls.join("\n"),
+                vc.smt(&pr, logic)?
+            );
+            res.push(Vc {
+                index,
+                kind,
+                script,
+            });
+        }
+    }
+    Ok(res)
+}
+
+#[test]
+fn smt_counter() {
+    use super::p_comb::Parser;
+    let r = Ring::new();
+    let e = program()
+        .parse(
+            r#"
+            x = 0; y = 0;
+            while (x - n != 0) {
+                x = x + 1;
+                y = y + 2;
+            }
+            z = 0;
+            while (z - y != 0) {
+                z = z + 1;
+            }
+            "#,
+        )
+        .map_or(E::Skip, |(_i, c)| c);
+    let e = convert_from_parseresult(&e, &r);
+    let v = |s: &str| Poly::from((r.borrow().var_by_name(s).unwrap(), &r));
+    let two = Poly::from((super::coef::C::new(2, 1), &r));
+    let invs = vec![vec![v("y") - two * v("x")], vec![]];
+    let vcs = smt_vcs(&e, &invs, Logic::Int, &r).unwrap();
+    for vc in &vcs {
+        println!("{}", vc.script);
+    }
+    assert!(vcs.len() == 4);
+    assert!(vcs[0].kind == VcKind::Initiation && vcs[1].kind == VcKind::Consecution);
+    assert!(vcs
+        .iter()
+        .all(|vc| vc.script.contains("(declare-const y Int)")));
+    // x = y = 0 を代入すると 0 = 0
+    assert!(vcs[0].script.contains("(assert (not (= 0 0)))"));
+    // 本体の後では 2(x + 1) - (y + 2) = 2x - y
+    assert!(vcs[1].script.contains(
+        "(assert (not (=> (and (= (+ (* 2 x) (* (- 1) y)) 0) (not (= (+ x (* (- 1) n)) 0))) (= (+ (* 2 x) (* (- 1) y)) 0))))"
+    ));
+    // 二つ目のループは不変式がないので, 前のループの出口からの条件も自明
+    assert!(vcs[2].script.contains("(assert (not true))"));
+    let vcs = smt_vcs(&e, &invs, Logic::Real, &r).unwrap();
+    assert!(vcs[0].script.contains("(set-logic QF_NRA)"));
+    // 分母の lcm が i64 を超えると, 整数係数にそろえられない. n は代入されないので式はそのまま残る
+    let c = |d| Poly::from((C::new(1, d), &r));
+    let big = c(1_000_000_007) * v("n").pow(3)
+        + c(998_244_353) * v("n").pow(2)
+        + c(1_000_000_009) * v("n")
+        + c(999_999_937);
+    let invs = vec![vec![big], vec![]];
+    assert!(smt_vcs(&e, &invs, Logic::Int, &r).unwrap_err() == CoefOverflow);
+    assert!(smt_vcs(&e, &invs, Logic::Real, &r).is_ok());
+}
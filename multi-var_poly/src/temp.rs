@@ -3,15 +3,14 @@ use super::mon::*;
 use super::poly::*;
 use super::ring::*;
 use itertools::Itertools;
-use std::cell::RefCell;
+use serde::Serialize;
 use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
-use std::rc::Rc;
 #[derive(PartialEq, Clone)]
 pub struct Temp {
     pub mons: Vec<Reverse<Mon<LinExp>>>,
-    pub r: Rc<RefCell<Ring>>,
+    pub r: RingRef,
 }
 impl Eq for Temp {}
 impl Hash for Temp {
@@ -52,13 +51,13 @@ impl std::fmt::Debug for Temp {
 
 // constructers
 impl Temp {
-    pub fn zero(r: &Rc<RefCell<Ring>>) -> Temp {
+    pub fn zero(r: &RingRef) -> Temp {
         Temp {
             mons: vec![Reverse(Mon::zero(r))],
             r: r.clone(),
         }
     }
-    fn one(r: &Rc<RefCell<Ring>>) -> Temp {
+    fn one(r: &RingRef) -> Temp {
         Temp {
             mons: vec![Reverse(Mon::one(r))],
             r: r.clone(),
@@ -75,14 +74,14 @@ fn zero_is_identity_of_add() {
 }
 
 // construct Temp with extend Rings
-impl From<(Vec<Mon<LinExp>>, &Rc<RefCell<Ring>>)> for Temp {
-    fn from(a: (Vec<Mon<LinExp>>, &Rc<RefCell<Ring>>)) -> Self {
+impl From<(Vec<Mon<LinExp>>, &RingRef)> for Temp {
+    fn from(a: (Vec<Mon<LinExp>>, &RingRef)) -> Self {
         let (a, r) = a;
         let mut mons = vec![];
         for m in a {
             for pt in &m.coef.terms {
                 match pt.par {
-                    Some(p) => drop(r.borrow_mut().pars.insert(p)),
+                    Some(p) => r.borrow_mut().pextend(vec![p]),
                     None => (),
                 }
             }
@@ -103,35 +102,25 @@ impl Temp {
         self.mons[0].0 == Mon::zero(&self.r)
     }
     fn sort_sumup(&mut self) {
-        // dummy monomial
-        let dm = Reverse(Mon::<LinExp>::zero(&self.r));
-        self.mons.push(dm.clone());
-        // 0を追加して, 最後にまとめて消す
-        self.mons.sort();
-        for i in 1..self.mons.len() {
-            // TODO: 突貫工事, どこかで生じる空リスト係数をなくせ
-            if self.mons[i].0.coef.terms.len() == 0 {
-                self.mons[i] = dm.clone();
+        // 同じ単項式を隣り合わせに並べてから, 係数をまとめる
+        let mut mons = std::mem::take(&mut self.mons);
+        mons.sort_by(|m1, m2| m2.0.cmp_vars(&m1.0));
+        let mut res: Vec<Reverse<Mon<LinExp>>> = vec![];
+        for Reverse(m) in mons {
+            // どこかで生じる空リスト係数も 0 とみなす
+            if m.coef.is_zero() || m.coef.terms.is_empty() {
+                continue;
             }
-            if !(self.mons[i - 1] > self.mons[i]) && !(self.mons[i - 1] < self.mons[i]) {
-                let c = self.mons[i].0.coef.clone();
-                self.mons[i - 1].0.coef += c;
-                self.mons[i] = dm.clone();
-                if self.mons[i - 1].0.coef == LinExp::zero() {
-                    self.mons[i - 1] = dm.clone();
-                }
+            match res.last_mut() {
+                Some(Reverse(l)) if l.cmp_vars(&m).is_eq() => l.coef += m.coef,
+                _ => res.push(Reverse(m)),
             }
         }
-        self.mons.sort();
-        while let Some(m) = self.mons.pop() {
-            if m != dm {
-                self.mons.push(m);
-                break;
-            }
-        }
-        if self.mons.len() == 0 {
-            self.mons.push(dm);
+        res.retain(|m| !m.0.coef.is_zero());
+        if res.is_empty() {
+            res.push(Reverse(Mon::zero(&self.r)));
         }
+        self.mons = res;
     }
     // x に関しての昇順でソート
     fn sort_by_var(&mut self, v: Var) {
@@ -148,7 +137,7 @@ impl Temp {
         m.0.vars.iter().fold(0, |s, (_, v)| s + v)
     }
 
-    pub fn most_gen(d: usize, r: &Rc<RefCell<Ring>>) -> Temp {
+    pub fn most_gen(d: usize, r: &RingRef) -> Temp {
         let v: Vec<Var> = r
             .borrow_mut()
             .vars
@@ -156,29 +145,88 @@ impl Temp {
             .into_iter()
             .map(|(v, _s)| v)
             .collect();
-        let mut cnt = r.borrow().pars.len();
         let mut dummy_poly = Poly::one(r);
         for (v, _s) in &r.borrow().vars {
             dummy_poly += Poly::from((*v, r));
         }
         dummy_poly = dummy_poly.pow(d);
+        let fresh_pars = r.borrow_mut().fresh_pars(dummy_poly.mons.len());
         let mut res = Temp::zero(r);
-        let mut fresh_pars = vec![];
         // これをすると, 定数項からIndexがついていく
+        let mut fresh_pars = fresh_pars.into_iter();
         while let Some(Reverse(m)) = dummy_poly.mons.pop() {
-            let fp = Par::new(cnt);
-            cnt += 1;
+            let fp = fresh_pars.next().expect("fresh parameter");
             let new_m = Mon::from((fp, m.vars, r));
-            fresh_pars.push(fp);
             res.mons.push(Reverse(new_m));
         }
         // ソートは別にしなくていいんだけどね(popじゃなくて順番を管理すればよい)
-        r.borrow_mut().pextend(fresh_pars);
         res.sort_sumup();
         res
     }
+    // 単項式ごとに新しいパラメーターを付ける. most_gen と同じく定数項の側から番号をつける
+    pub fn from_mons(ms: &[HashMap<Var, usize>], r: &RingRef) -> Temp {
+        let mut ms: Vec<Mon<C>> = ms.iter().map(|m| Mon::from((m.clone(), r))).collect();
+        ms.sort_by(|a, b| a.cmp_vars(b));
+        ms.dedup_by(|a, b| a.cmp_vars(b).is_eq());
+        let fresh_pars = r.borrow_mut().fresh_pars(ms.len());
+        let mons = ms
+            .into_iter()
+            .zip(fresh_pars)
+            .map(|(m, p)| Mon::from((p, m.vars, r)))
+            .collect();
+        Temp::from((mons, r))
+    }
+    // vs だけを使った全次数 d 以下の単項式
+    pub fn most_gen_in(d: usize, vs: &[Var], r: &RingRef) -> Temp {
+        let bounds: Vec<(Var, usize)> = vs.iter().map(|v| (*v, d)).collect();
+        Temp::bounded(&bounds, d, r)
+    }
+    // 変数ごとの次数の上限と, 全次数の上限 d
+    pub fn bounded(bounds: &[(Var, usize)], d: usize, r: &RingRef) -> Temp {
+        let ms: Vec<HashMap<Var, usize>> = lattice_box(bounds)
+            .into_iter()
+            .filter(|e| e.iter().sum::<usize>() <= d)
+            .map(|e| to_mon_map(bounds, &e))
+            .collect();
+        Temp::from_mons(&ms, r)
+    }
+    // 頂点 vs の凸包 (Newton 多面体) に入る格子点の単項式
+    pub fn newton(vs: &[HashMap<Var, usize>], r: &RingRef) -> Temp {
+        let mut vars: Vec<Var> = vs.iter().flat_map(|m| m.keys().copied()).collect();
+        vars.sort();
+        vars.dedup();
+        let pts: Vec<Vec<C>> = vs
+            .iter()
+            .map(|m| {
+                vars.iter()
+                    .map(|v| C::from_integer(*m.get(v).unwrap_or(&0) as i64))
+                    .collect()
+            })
+            .collect();
+        let bounds: Vec<(Var, usize)> = vars
+            .iter()
+            .map(|v| {
+                (
+                    *v,
+                    vs.iter()
+                        .map(|m| *m.get(v).unwrap_or(&0))
+                        .max()
+                        .unwrap_or(0),
+                )
+            })
+            .collect();
+        let ms: Vec<HashMap<Var, usize>> = lattice_box(&bounds)
+            .into_iter()
+            .filter(|e| {
+                let q: Vec<C> = e.iter().map(|d| C::from_integer(*d as i64)).collect();
+                in_hull(&pts, &q)
+            })
+            .map(|e| to_mon_map(&bounds, &e))
+            .collect();
+        Temp::from_mons(&ms, r)
+    }
     // randomized version
-    // pub fn most_gen(d: usize, r: &Rc<RefCell<Ring>>) -> Temp {
+    // pub fn most_gen(d: usize, r: &RingRef) -> Temp {
     //     let v: Vec<Var> = r
     //         .borrow()
     //         .vars
@@ -212,10 +260,75 @@ impl Temp {
     //     Temp { mons, r: r.clone() }
     // }
 
+    // self - q other. q は次数 tdeg(self) - tdeg(other) の一般テンプレートで, 係数は新しいパラメーター
+    pub fn try_rem_par(&self, other: &Poly) -> Result<Rem, RemError> {
+        if other.is_zero() {
+            return Err(RemError::ZeroDivisor);
+        }
+        if other.tdeg() > self.tdeg() {
+            return Err(RemError::DegreeTooHigh {
+                temp: self.tdeg(),
+                divisor: other.tdeg(),
+            });
+        }
+        let q = Temp::most_gen(self.tdeg() - other.tdeg(), &self.r);
+        let fresh = q.get_pars().len();
+        Ok(Rem {
+            rem: q * (-other.clone()) + self.clone(),
+            fresh,
+        })
+    }
+
+    // 割る式の方が次数が高ければ, 掛けられる商は 0 だけなので self のまま
     pub fn rem_par(&self, other: Poly) -> Temp {
-        let diff = self.tdeg() - other.tdeg();
-        let q = Temp::most_gen(diff, &self.r);
-        q * (-other) + self.clone()
+        match self.try_rem_par(&other) {
+            Ok(Rem { rem, .. }) => rem,
+            Err(RemError::DegreeTooHigh { .. }) => self.clone(),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    // other の先頭項で割れる単項式がなくなるまで割る. 係数は 1 次式のままで, パラメーターは増えない
+    pub fn rem_nf(&self, other: &Poly) -> Result<Temp, RemError> {
+        if other.is_zero() {
+            return Err(RemError::ZeroDivisor);
+        }
+        let lm = &other.mons[0].0;
+        let inv = C::one() / lm.coef;
+        let mut res = self.clone();
+        loop {
+            let m = res.mons.iter().map(|m| &m.0).find(|m| {
+                lm.vars
+                    .iter()
+                    .all(|(v, d)| m.vars.get(v).is_some_and(|e| e >= d))
+            });
+            let m = match m {
+                Some(m) if !m.coef.is_zero() => m,
+                _ => return Ok(res),
+            };
+            let mut vars = m.vars.clone();
+            for (v, d) in &lm.vars {
+                let e = vars.get_mut(v).unwrap();
+                *e -= d;
+                if *e == 0 {
+                    vars.remove(v);
+                }
+            }
+            let mut t: Mon<LinExp> = Mon::from((vars, &self.r));
+            t.coef = m.coef.clone() * inv;
+            let t = Temp {
+                mons: vec![Reverse(t)],
+                r: self.r.clone(),
+            };
+            res += t * (-other.clone());
+        }
+    }
+
+    pub fn rem_with(&self, other: &Poly, mode: RemMode) -> Result<Rem, RemError> {
+        match mode {
+            RemMode::Quotient => self.try_rem_par(other),
+            RemMode::NormalForm => self.rem_nf(other).map(|rem| Rem { rem, fresh: 0 }),
+        }
     }
 
     pub fn subs(mut self, v: Var, other: Poly) -> Temp {
@@ -223,22 +336,115 @@ impl Temp {
         let mut res = Temp::zero(&self.r);
         let mut base = Poly::one(&self.r);
         let mut cur = 0;
-        for m in &mut self.mons {
-            match m.0.vars.remove(&v) {
-                Some(d) => {
-                    assert!(d > 0);
-                    if cur < d {
-                        base *= other.pow(d - cur);
-                        cur = d;
-                    }
-                }
-                None => (),
+        // v の次数ごとにまとめてから冪を掛ける.
+        // 同じ v^d で割っても順序は変わらないので, グループはソート済みのまま
+        let mut group = vec![];
+        for Reverse(mut m) in std::mem::take(&mut self.mons) {
+            let d = m.vars.remove(&v).unwrap_or(0);
+            if d != cur {
+                let t = Temp {
+                    mons: std::mem::take(&mut group),
+                    r: self.r.clone(),
+                };
+                res += t * base.clone();
+                base *= other.pow(d - cur);
+                cur = d;
+            }
+            group.push(Reverse(m));
+        }
+        let t = Temp {
+            mons: group,
+            r: self.r.clone(),
+        };
+        res += t * base;
+        res
+    }
+
+    // 並行代入. subs を一つずつ使うと前の代入の結果にも代入してしまう
+    pub fn subs_many(&self, map: &HashMap<Var, Poly>) -> Temp {
+        let mut cache = HashMap::new();
+        let mut res = Temp::zero(&self.r);
+        for (key, mons) in split_mons(&self.mons, |v| map.contains_key(v)) {
+            let f = subs_factor(&key, map, &mut cache, &self.r);
+            res += Temp {
+                mons,
+                r: self.r.clone(),
+            } * f;
+        }
+        res
+    }
+
+    pub fn diff(&self, v: Var) -> Temp {
+        Temp {
+            mons: diff_mons(&self.mons, v, &self.r),
+            r: self.r.clone(),
+        }
+    }
+    pub fn gradient(&self, vs: &[Var]) -> Vec<Temp> {
+        vs.iter().map(|v| self.diff(*v)).collect()
+    }
+    pub fn jacobian(ts: &[Temp], vs: &[Var]) -> Vec<Vec<Temp>> {
+        ts.iter().map(|t| t.gradient(vs)).collect()
+    }
+
+    // 変数を付け替えて別の環に移す. パラメーターはそのまま (Ring::merge の後は map_pars も使う)
+    pub fn map_vars(&self, map: &HashMap<Var, Var>, r: &RingRef) -> Temp {
+        let mut mons = vec![];
+        for Reverse(m) in &self.mons {
+            let vars: HashMap<Var, usize> = m.vars.iter().map(|(v, d)| (map[v], *d)).collect();
+            let mut new_m: Mon<LinExp> = Mon::from((vars, r));
+            new_m.coef = m.coef.clone();
+            mons.push(new_m);
+        }
+        Temp::from((mons, r))
+    }
+    // パラメーターを付け替える
+    pub fn map_pars(&self, map: &HashMap<Par, Par>) -> Temp {
+        let mut mons = vec![];
+        for Reverse(m) in &self.mons {
+            let mut new_m = m.clone();
+            let terms: Vec<ParTerm> = m
+                .coef
+                .terms
+                .iter()
+                .map(|pt| ParTerm {
+                    par: pt.par.map(|p| map[&p]),
+                    coef: pt.coef,
+                })
+                .collect();
+            new_m.coef = LinExp::from(terms);
+            mons.push(new_m);
+        }
+        Temp::from((mons, &self.r))
+    }
+
+    // 変数に値を入れると, パラメーターの一次式になる
+    pub fn eval_vars(&self, pt: &HashMap<Var, C>) -> LinExp {
+        let mut res = LinExp::zero();
+        for Reverse(m) in &self.mons {
+            let (c, rest) = m.eval_vars(pt);
+            if let Some(v) = rest.keys().next() {
+                panic!("no value for {}", self.r.borrow().vars[v]);
             }
-            res += Temp::from((vec![m.0.clone()], &self.r)) * base.clone();
+            res += m.coef.clone() * c;
         }
         res
     }
 
+    pub fn get_pars(&self) -> Vec<Par> {
+        let mut pars = vec![];
+        for Reverse(m) in &self.mons {
+            for pt in &m.coef.terms {
+                if let Some(p) = pt.par {
+                    pars.push(p);
+                }
+            }
+        }
+        pars.sort();
+        pars.dedup();
+        pars
+    }
+
     pub fn subs_pars(&self, sol: Vec<(Par, LinExp)>) -> Temp {
         let sol_map = sol.into_iter().collect::<HashMap<Par, LinExp>>();
         // 各単項式の
@@ -267,38 +473,26 @@ impl std::ops::Add<Temp> for Temp {
     type Output = Temp;
 
     fn add(mut self, rhs: Temp) -> Self::Output {
-        self.mons.extend(rhs.mons);
-        self.sort_sumup();
+        self.r.assert_same(&rhs.r);
+        self.mons = merge_mons(self.mons, rhs.mons, &self.r);
         self
     }
 }
 
 impl std::ops::AddAssign<Temp> for Temp {
     fn add_assign(&mut self, rhs: Temp) {
-        *self = self.clone() + rhs;
+        self.r.assert_same(&rhs.r);
+        let lhs = std::mem::take(&mut self.mons);
+        self.mons = merge_mons(lhs, rhs.mons, &self.r);
     }
 }
 
 impl std::ops::Mul<Poly> for Temp {
     type Output = Temp;
     fn mul(mut self, other: Poly) -> Self::Output {
-        if other.is_zero() || self.is_zero() {
-            Temp::zero(&self.r)
-        } else {
-            let mut new_terms: Vec<Reverse<Mon<LinExp>>> = vec![];
-            for m in &other.mons {
-                for tm in &self.mons {
-                    new_terms.push(Reverse(tm.0.clone() * m.0.clone()))
-                }
-            }
-            if new_terms.len() == 0 {
-                Temp::zero(&self.r)
-            } else {
-                self.mons = new_terms;
-                self.sort_sumup();
-                self
-            }
-        }
+        self.r.assert_same(&other.r);
+        self.mons = mul_mons(&self.mons, &other.mons, &self.r);
+        self
     }
 }
 
@@ -331,6 +525,127 @@ impl std::ops::Neg for Temp {
     }
 }
 
+/*
+Remainders
+*/
+// ガード p で割った余りの作り方. Quotient は商を一般テンプレートで置く (新しいパラメーターが増える).
+// NormalForm は p の先頭項で割り切る. 一つの多項式はそれだけで Gröbner 基底なので, 余りが 0 なのは p の倍数のときだけ
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RemMode {
+    Quotient,
+    NormalForm,
+}
+
+#[derive(Clone, Debug)]
+pub struct Rem {
+    pub rem: Temp,
+    // 商のために確保したパラメーターの数
+    pub fresh: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RemError {
+    ZeroDivisor,
+    DegreeTooHigh { temp: usize, divisor: usize },
+}
+
+impl std::fmt::Display for RemError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RemError::ZeroDivisor => write!(f, "division of a template by zero"),
+            RemError::DegreeTooHigh { temp, divisor } => write!(
+                f,
+                "divisor of degree {} exceeds the template degree {}",
+                divisor, temp
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RemError {}
+
+/*
+Sparse Templates
+*/
+
+// 各変数の次数が上限以下の指数の組を全部
+fn lattice_box(bounds: &[(Var, usize)]) -> Vec<Vec<usize>> {
+    bounds
+        .iter()
+        .map(|(_, b)| 0..=*b)
+        .multi_cartesian_product()
+        .collect()
+}
+fn to_mon_map(bounds: &[(Var, usize)], e: &[usize]) -> HashMap<Var, usize> {
+    bounds
+        .iter()
+        .zip(e)
+        .filter(|(_, d)| **d > 0)
+        .map(|((v, _), d)| (*v, *d))
+        .collect()
+}
+
+// q が pts の凸包に入るか. λ >= 0, Σ λ = 1, Σ λ_i pts_i = q が解けるかを,
+// 人工変数の和を最小化する単体法 (Bland の規則) で調べる
+fn in_hull(pts: &[Vec<C>], q: &[C]) -> bool {
+    if pts.is_empty() {
+        return false;
+    }
+    let (m, k) = (q.len() + 1, pts.len());
+    // 各行は [λ_1..λ_k, a_1..a_m, 右辺]
+    let mut t: Vec<Vec<C>> = (0..m)
+        .map(|i| {
+            let mut row: Vec<C> = pts
+                .iter()
+                .map(|p| if i < q.len() { p[i] } else { C::one() })
+                .collect();
+            row.extend((0..m).map(|j| if i == j { C::one() } else { C::zero() }));
+            row.push(if i < q.len() { q[i] } else { C::one() });
+            row
+        })
+        .collect();
+    let mut basis: Vec<usize> = (k..k + m).collect();
+    // 目的関数の被約費用
+    let mut z: Vec<C> = (0..=k + m)
+        .map(|j| {
+            if (k..k + m).contains(&j) {
+                C::zero()
+            } else {
+                -t.iter().fold(C::zero(), |acc, row| acc + row[j])
+            }
+        })
+        .collect();
+    while let Some(j) = (0..k + m).find(|j| z[*j] < C::zero()) {
+        let i = match (0..m).filter(|i| t[*i][j] > C::zero()).min_by(|a, b| {
+            let (ra, rb) = (t[*a][k + m] / t[*a][j], t[*b][k + m] / t[*b][j]);
+            ra.cmp(&rb).then(basis[*a].cmp(&basis[*b]))
+        }) {
+            Some(i) => i,
+            None => break,
+        };
+        let piv = t[i][j];
+        for x in t[i].iter_mut() {
+            *x /= piv;
+        }
+        let pivot_row = t[i].clone();
+        for (l, row) in t.iter_mut().enumerate() {
+            if l != i && !row[j].is_zero() {
+                let f = row[j];
+                for (x, p) in row.iter_mut().zip(&pivot_row) {
+                    *x -= f * *p;
+                }
+            }
+        }
+        let f = z[j];
+        for (x, p) in z.iter_mut().zip(&pivot_row) {
+            *x -= f * *p;
+        }
+        basis[i] = j;
+    }
+    z[k + m].is_zero()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -355,7 +670,7 @@ mod tests {
         let p1 = Temp::from((vec![ax2, cxy, yz, y2.clone()], &r));
         let p2 = Temp::from((vec![bx2, dxy, y2], &r));
         // もれなくだぶりなく拡張されている
-        assert!(r.as_ref().borrow().pars == pars.into_iter().collect());
+        assert!(r.borrow().pars == pars.into_iter().collect());
         assert!(p1.tdeg() == 2);
         assert!(p2.tdeg() == 2);
 
@@ -385,6 +700,90 @@ mod tests {
         assert!(m.tdeg() == 4);
     }
 
+    #[test]
+    fn check_subs_many() {
+        let r = Ring::new();
+        let x = r.borrow_mut().vextend("x".to_string());
+        let y = r.borrow_mut().vextend("y".to_string());
+        let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+        let t = Temp::most_gen(2, &r);
+        // 入れ替えを二回すると元に戻る
+        let swap: HashMap<Var, Poly> = vec![(x, py.clone()), (y, px.clone())].into_iter().collect();
+        let s = t.subs_many(&swap);
+        println!("{:?}\n{:?}", t, s);
+        assert!(s != t);
+        assert!(s.subs_many(&swap) == t);
+        // 先に代入した式に後の変数が出てこなければ, subs を続けたものと同じ
+        let one = Poly::one(&r);
+        let m: HashMap<Var, Poly> = vec![(x, one.clone() + one.clone()), (y, px.pow(2))]
+            .into_iter()
+            .collect();
+        let seq = t.clone().subs(x, one.clone() + one).subs(y, px.pow(2));
+        assert!(t.subs_many(&m) == seq);
+    }
+
+    #[test]
+    fn check_temp_diff() {
+        let r = Ring::new();
+        let x = r.borrow_mut().vextend("x".to_string());
+        let y = r.borrow_mut().vextend("y".to_string());
+        // a5 x^2 + a4 xy + a3 y^2 + a2 x + a1 y + a0
+        let t = Temp::most_gen(2, &r);
+        let pars: Vec<Par> = (0..6).map(Par::new).collect();
+        let dx = Temp::from((
+            vec![
+                Mon::from((pars[5], vec![(x, 1)], &r)) * C::new(2, 1),
+                Mon::from((pars[4], vec![(y, 1)], &r)),
+                Mon::from((pars[2], vec![], &r)),
+            ],
+            &r,
+        ));
+        println!("d/dx {:?} = {:?}", t, t.diff(x));
+        assert!(t.diff(x) == dx);
+        // 二階微分は定数
+        assert!(t.diff(x).diff(x).mons[0].0.coef == LinExp::from(pars[5]) * C::new(2, 1));
+        assert!(t.diff(x).diff(x).diff(y) == Temp::zero(&r));
+        let vs = r.borrow().var_list();
+        let j = Temp::jacobian(&[t.clone()], &vs);
+        assert!(j[0] == t.gradient(&vs));
+        assert!(j[0][1].get_pars() == vec![pars[1], pars[3], pars[4]]);
+    }
+
+    #[test]
+    fn check_eval_vars() {
+        let r = Ring::new();
+        let x = r.borrow_mut().vextend("x".to_string());
+        let y = r.borrow_mut().vextend("y".to_string());
+        // a0 + a1 y + a2 x + a3 y^2 + a4 xy + a5 x^2 (定数項から番号が付く)
+        let t = Temp::most_gen(2, &r);
+        let pt: HashMap<Var, C> = vec![(x, C::new(2, 1)), (y, C::new(-1, 1))]
+            .into_iter()
+            .collect();
+        let le = t.eval_vars(&pt);
+        // 各パラメーターの係数は, 対応する単項式の値
+        let mut expected = LinExp::zero();
+        for Reverse(m) in &t.mons {
+            let v: C = m
+                .vars
+                .iter()
+                .map(|(v, d)| num_traits::pow(pt[v], *d))
+                .product();
+            expected += m.coef.clone() * v;
+        }
+        assert!(le == expected);
+        assert!(le.terms.len() == 6);
+        // パラメーターに解を入れてから評価しても同じ
+        let sol: Vec<(Par, LinExp)> = t
+            .get_pars()
+            .into_iter()
+            .map(|p| (p, LinExp::one() * C::new(p.id as i64, 1)))
+            .collect();
+        let c = t.subs_pars(sol).eval_vars(&pt);
+        // 0 - 1 + 2*2 + 3*1 + 4*(-2) + 5*4
+        assert!(c == LinExp::one() * C::new(18, 1));
+        assert!(Temp::zero(&r).eval_vars(&pt).is_zero());
+    }
+
     #[test]
     fn check_subs_mostgen() {
         // 0 -> x, 1 -> y, 2 -> z
@@ -399,7 +798,7 @@ mod tests {
         */
         let p1 = Temp::most_gen(2, &r);
         // 3 variable, 2 degree => 4H2 == 5C2 == 10
-        assert!(r.as_ref().borrow().pars.len() == 10);
+        assert!(r.borrow().pars.len() == 10);
         println!("{:?}", p1);
         assert!(p1.tdeg() == 2);
 
@@ -514,4 +913,107 @@ mod tests {
         println!("{:?}", g1);
         println!("{:?}", g2);
     }
+
+    #[test]
+    fn check_sparse_templates() {
+        let r = Ring::new();
+        let x = r.borrow_mut().vextend("x".to_string());
+        let y = r.borrow_mut().vextend("y".to_string());
+        let z = r.borrow_mut().vextend("z".to_string());
+        let m = |es: Vec<(Var, usize)>| es.into_iter().collect::<HashMap<Var, usize>>();
+        // 明示した単項式. 重複は一つにまとめる
+        let t = Temp::from_mons(
+            &[
+                m(vec![(x, 1), (y, 1)]),
+                m(vec![(x, 1)]),
+                m(vec![]),
+                m(vec![(x, 1)]),
+            ],
+            &r,
+        );
+        println!("{:?}", t);
+        assert!(t.mons.len() == 3 && t.get_pars().len() == 3);
+        // x, y だけの二次: 1, x, y, x^2, xy, y^2
+        let t = Temp::most_gen_in(2, &[x, y], &r);
+        assert!(t.mons.len() == 6);
+        assert!(t.mons.iter().all(|m| !m.0.vars.contains_key(&z)));
+        // x は一次まで, y は二次まで, 全次数 2 まで: 1, x, y, xy, y^2
+        let t = Temp::bounded(&[(x, 1), (y, 2)], 2, &r);
+        println!("{:?}", t);
+        assert!(t.mons.len() == 5);
+        // 頂点 1, x^2, y^2 の三角形: 1, x, y, x^2, xy, y^2 (全次数 2 と同じ)
+        let t = Temp::newton(&[m(vec![]), m(vec![(x, 2)]), m(vec![(y, 2)])], &r);
+        assert!(t.mons.len() == 6);
+        // 頂点 x, y z の線分: x と yz の二点だけ
+        let t = Temp::newton(&[m(vec![(x, 1)]), m(vec![(y, 1), (z, 1)])], &r);
+        println!("{:?}", t);
+        assert!(t.mons.len() == 2);
+        // 四角形 1, x^2, y^2, x^2 y^2: 9 点
+        let t = Temp::newton(
+            &[
+                m(vec![]),
+                m(vec![(x, 2)]),
+                m(vec![(y, 2)]),
+                m(vec![(x, 2), (y, 2)]),
+            ],
+            &r,
+        );
+        assert!(t.mons.len() == 9);
+        // most_gen よりずっと少ない
+        assert!(Temp::most_gen(2, &r).mons.len() == 10);
+    }
+
+    #[test]
+    fn check_remainders() {
+        let r = Ring::new();
+        let x = r.borrow_mut().vextend("x".to_string());
+        let y = r.borrow_mut().vextend("y".to_string());
+        let (px, py) = (Poly::from((x, &r)), Poly::from((y, &r)));
+        let g = Temp::most_gen(1, &r);
+        let p = px.clone() * py.clone() - Poly::one(&r);
+        // 1 次のテンプレートは 2 次式で割れない
+        assert!(
+            g.try_rem_par(&p).unwrap_err()
+                == RemError::DegreeTooHigh {
+                    temp: 1,
+                    divisor: 2
+                }
+        );
+        assert!(g.rem_par(p.clone()) == g);
+        assert!(g.try_rem_par(&Poly::zero(&r)).unwrap_err() == RemError::ZeroDivisor);
+        // 商は定数の一般テンプレートなので 1 個増える
+        let before = r.borrow().par_count();
+        let q = g.try_rem_par(&(px.clone() - py.clone())).unwrap();
+        assert!(q.fresh == 1 && r.borrow().par_count() == before + 1);
+        // 正規形ではパラメーターは増えず, x は y に置き換わる
+        let g2 = Temp::most_gen(2, &r);
+        let before = r.borrow().par_count();
+        let nf = g2.rem_nf(&(px.clone() - py.clone())).unwrap();
+        println!("{:?}", nf);
+        assert!(r.borrow().par_count() == before);
+        assert!(nf.mons.iter().all(|m| !m.0.vars.contains_key(&x)));
+        // 倍数の余りは 0
+        let h = Temp::from((vec![Mon::from((Par::new(0), vec![(y, 1)], &r))], &r)) * p.clone();
+        assert!(h.rem_nf(&p).unwrap() == Temp::zero(&r));
+    }
+
+    #[test]
+    fn map_pars_after_merge() {
+        let r1 = Ring::new();
+        r1.borrow_mut().vextend("x".to_string());
+        let r2 = Ring::new();
+        r2.borrow_mut().vextend("x".to_string());
+        let t1 = Temp::most_gen(1, &r1);
+        let t2 = Temp::most_gen(1, &r2);
+        // どちらも a[0] + a[1] x
+        assert!(t1.get_pars() == t2.get_pars());
+        let m = Ring::merge(&r1, &r2);
+        let s1 = t1.map_vars(&m.left, &m.ring).map_pars(&m.left_pars);
+        let s2 = t2.map_vars(&m.right, &m.ring).map_pars(&m.right_pars);
+        println!("{:?}\n{:?}", s1, s2);
+        assert!(s1.get_pars() == vec![Par::new(0), Par::new(1)]);
+        assert!(s2.get_pars() == vec![Par::new(2), Par::new(3)]);
+        // 足しても未知数は混ざらない
+        assert!((s1 + s2).get_pars().len() == 4);
+    }
 }
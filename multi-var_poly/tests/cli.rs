@@ -0,0 +1,49 @@
+use std::process::Command;
+
+// プログラムを一時ファイルに書いて, バイナリを走らせる
+fn run(name: &str, src: &str, args: &[&str]) -> std::process::Output {
+    let path =
+        std::env::temp_dir().join(format!("multi-var_poly-{}-{}.c", name, std::process::id()));
+    std::fs::write(&path, src).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_multi-var_poly"))
+        .args(args)
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    out
+}
+
+const COUNTER: &str = "x = 0; y = 0;\nwhile (x - n != 0) {\n    x = x + 1;\n    y = y + 2;\n}\n";
+
+#[test]
+fn deg_auto_json() {
+    for mode in &["quotient", "nf"] {
+        let out = run(
+            "auto",
+            COUNTER,
+            &["--deg", "auto", "--json", "--mode", mode],
+        );
+        assert!(out.status.success());
+        let s = String::from_utf8(out.stdout).unwrap();
+        println!("{}", s);
+        // 標準出力は JSON だけ
+        let v: serde_json::Value = serde_json::from_str(&s).unwrap();
+        assert!(v["stop"] == "saturated");
+        assert!(v["invariants"][0]["found_at"] == 1);
+        assert!(v["invariants"][0]["text"] == "2*x - y");
+    }
+}
+
+#[test]
+fn cas_script() {
+    let out = run("cas", COUNTER, &["--cas", "singular"]);
+    assert!(out.status.success());
+    let s = String::from_utf8(out.stdout).unwrap();
+    println!("{}", s);
+    // 不変式, ループ頭のテンプレート, 連立方程式の順
+    let at = |pat: &str| s.find(pat).unwrap();
+    assert!(at("ring loop0Ring = 0, (x, y, n), dp;") < at("ring head0Ring = (0, "));
+    assert!(at("ring head0Ring = (0, ") < at("ring eqsRing = 0, ("));
+    assert!(s.contains("std(eqs);"));
+}